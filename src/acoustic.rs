@@ -0,0 +1,157 @@
+use crate::finite_volume::Face;
+use crate::grid::Grid;
+use crate::{calculate_pressure, sound_speed, Cell, Real, Settings};
+
+// The still ambient gas everything is a small disturbance of: its state, sound speed and
+// the enthalpy per mass the momentum carries
+struct Background {
+    state: Cell,
+    sound: Real,
+    enthalpy: Real,
+}
+
+impl Background {
+    fn new(settings: &Settings) -> Background {
+        let state = settings.ambient();
+        let pressure = calculate_pressure(&state, settings.gamma);
+        Background {
+            state,
+            sound: sound_speed(&state, settings.gamma),
+            enthalpy: (state.energy + pressure) / state.density,
+        }
+    }
+
+    // The Euler flux linearized about the background: the mass m, the pressure
+    // disturbance pushing on the normal momentum, nothing carried along by the flow, and the
+    // energy h0 m. With the pressure read off the energy alone (the kinetic part is second
+    // order) every wave runs at c0 and none of them steepen.
+    fn flux(&self, cell: &Cell, normal_x: bool, gamma: Real) -> Cell {
+        let pressure = (gamma - 1.0) * (cell.energy - self.state.energy);
+        let (mass, push_x, push_y) = if normal_x {
+            (cell.momentum_x, pressure, 0.0)
+        } else {
+            (cell.momentum_y, 0.0, pressure)
+        };
+        Cell {
+            density: mass,
+            momentum_x: push_x,
+            momentum_y: push_y,
+            energy: self.enthalpy * mass,
+        }
+    }
+
+    // Rusanov again, the dissipation scaled by c0 everywhere since that's the only speed
+    // there is
+    fn face_flux(&self, left: Cell, right: Cell, normal_x: bool, gamma: Real) -> Cell {
+        (self.flux(&left, normal_x, gamma) + self.flux(&right, normal_x, gamma)) / 2.0
+            - (right - left) * (self.sound / 2.0)
+    }
+}
+
+// The left, right, up and down faces of the fluid cell (x, y), the ghosts the same as the
+// finite volume scheme's: mirror images in walls and obstacles, more of the same past the
+// left edge (and open top and bottom ones) and the outlet's past the right
+pub fn faces(
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    settings: &Settings,
+    x: usize,
+    y: usize,
+) -> [Face; 4] {
+    faces_with(&Background::new(settings), grid, solid, settings, x, y)
+}
+
+fn faces_with(
+    background: &Background,
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    settings: &Settings,
+    x: usize,
+    y: usize,
+) -> [Face; 4] {
+    let gamma = settings.gamma;
+    let periodic = settings.periodic || settings.wrap_edges;
+    let (width, height) = (grid.width(), grid.height());
+    let center = grid[y][x];
+    let mirrored = |normal_x: bool| {
+        if normal_x {
+            Cell {
+                momentum_x: -center.momentum_x,
+                ..center
+            }
+        } else {
+            Cell {
+                momentum_y: -center.momentum_y,
+                ..center
+            }
+        }
+    };
+    let face = |dx: isize, dy: isize| {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        let normal_x = dx != 0;
+        let inside = (0..width as isize).contains(&nx) && (0..height as isize).contains(&ny);
+        let (neighbor, across) = if inside || periodic {
+            let (nx, ny) = (
+                nx.rem_euclid(width as isize) as usize,
+                ny.rem_euclid(height as isize) as usize,
+            );
+            if solid[ny][nx] {
+                (None, mirrored(normal_x))
+            } else {
+                (Some((nx, ny)), grid[ny][nx])
+            }
+        } else if dx > 0 {
+            (None, settings.outlet.outside(&center, gamma))
+        } else if dx != 0 || settings.open_edges {
+            (None, center)
+        } else {
+            (None, mirrored(normal_x))
+        };
+        // Coming in through the left and top, going out through the right and bottom
+        let flux = if dx + dy < 0 {
+            background.face_flux(across, center, normal_x, gamma)
+        } else {
+            background.face_flux(center, across, normal_x, gamma) * -1.0
+        };
+        Face {
+            neighbor,
+            across,
+            flux,
+        }
+    };
+    [face(-1, 0), face(1, 0), face(0, -1), face(0, 1)]
+}
+
+// The acoustic scheme's update: the finite volume one's loop with the linear flux, for
+// small disturbances of a still gas that should travel as clean waves. Gravity, local
+// timesteps and a second gas have no place in it, the scenario rejects them.
+pub fn calculate_fluxes(
+    grid: &[Vec<Cell>],
+    new_grid: &mut [Vec<Cell>],
+    solid: &[Vec<bool>],
+    solid_fraction: &[Vec<Real>],
+    settings: &Settings,
+    dt: Real,
+) {
+    let background = Background::new(settings);
+    for (new_row, row) in new_grid.iter_mut().zip(grid) {
+        new_row.copy_from_slice(row);
+    }
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            if solid[y][x] {
+                continue;
+            }
+            let [left, right, up, down] = faces_with(&background, grid, solid, settings, x, y);
+            let change = (left.flux + right.flux) + (up.flux + down.flux);
+            let open = 1.0 - solid_fraction[y][x];
+            new_grid[y][x] = grid[y][x] + change * (dt * open);
+        }
+    }
+}
+
+// The largest dt with a Courant number of 1, the same in every cell since the waves don't
+// ride on the flow
+pub fn cfl_dt(settings: &Settings) -> Real {
+    1.0 / Background::new(settings).sound
+}
@@ -0,0 +1,131 @@
+use crate::error::AppError;
+#[cfg(feature = "count-allocations")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Steps left out of the count at the start of a run, while the buffers that grow with it
+// (signals, logs, rayon's pool) find their size
+const WARMUP_STEPS: u64 = 10;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+// The system allocator, counting every allocation and reallocation on any thread
+#[cfg(feature = "count-allocations")]
+struct Counting;
+
+#[cfg(feature = "count-allocations")]
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+// Allocations and bytes so far, only counted with the count-allocations feature
+fn totals() -> (u64, u64) {
+    (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    )
+}
+
+// How much every step allocates past the warmup, against a cap of `limit` allocations a
+// step. The steady state step should reuse its buffers, so anything over 0 is a clone or
+// a temporary that crept into the hot path. Only exists in builds that count.
+pub struct AllocationCheck {
+    limit: u64,
+    explicit: bool,
+    started: (u64, u64),
+    worst: (u64, u64, u64), // Allocations, bytes, step
+    total: u64,
+    steps: u64,
+    warned: bool,
+}
+
+impl AllocationCheck {
+    pub fn new(limit: Option<u64>) -> Option<AllocationCheck> {
+        cfg!(feature = "count-allocations").then(|| AllocationCheck {
+            limit: limit.unwrap_or(0),
+            explicit: limit.is_some(),
+            started: (0, 0),
+            worst: (0, 0, 0),
+            total: 0,
+            steps: 0,
+            warned: false,
+        })
+    }
+
+    pub fn start(&mut self) {
+        self.started = totals();
+    }
+
+    // After the step `step` that began at the last start
+    pub fn finish(&mut self, step: u64) {
+        let (allocations, bytes) = totals();
+        if step <= WARMUP_STEPS {
+            return;
+        }
+        let (allocations, bytes) = (allocations - self.started.0, bytes - self.started.1);
+        self.total += allocations;
+        self.steps += 1;
+        if allocations > self.worst.0 {
+            self.worst = (allocations, bytes, step);
+        }
+        if allocations > self.limit && !self.warned {
+            eprintln!(
+                "Warning: step {} made {} heap allocations ({} bytes), over the cap of {}",
+                step, allocations, bytes, self.limit
+            );
+            self.warned = true;
+        }
+    }
+
+    // An error if the cap was set by hand and some step went over it
+    pub fn report(&self) -> Result<(), AppError> {
+        if self.steps == 0 {
+            println!(
+                "Allocations: the run ended within the {} warmup steps",
+                WARMUP_STEPS
+            );
+            return Ok(());
+        }
+        let (worst, bytes, step) = self.worst;
+        println!(
+            "Allocations: {:.1} a step on average over {} steps, most {} ({} bytes) at step {}",
+            self.total as f64 / self.steps as f64,
+            self.steps,
+            worst,
+            bytes,
+            step
+        );
+        if self.explicit && worst > self.limit {
+            return Err(AppError(format!(
+                "step {} made {} heap allocations, over the cap of {}",
+                step, worst, self.limit
+            )));
+        }
+        Ok(())
+    }
+}
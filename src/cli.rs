@@ -0,0 +1,577 @@
+use crate::diagnostics::{BreakCondition, HoldField};
+use crate::inflow::InflowLimit;
+use crate::{ring_hotspots, Hotspot, Real, Sampling, WallKind};
+use std::path::PathBuf;
+
+// How long each GIF frame shows, 25 frames a second. GIFs count in hundredths of a second.
+const DEFAULT_GIF_DELAY: u32 = 40;
+
+pub const USAGE: &str =
+    "usage: fluid [--headless] [--steps <n>] [--stop-at <time>] [--screenshot <path>]
+             [--measure-diffusion] [--measure-sound-speed] [--scenario <name>] [--scenario-file <path>] [--periodic] [--no-source]
+             [--grid-width <cells>] [--grid-height <cells>] [--cell-size <pixels>]
+             [--source-rate <rate>] [--source-velocity <v>]
+             [--conduction <rate>] [--solid-heat-capacity <c>] [--wall slip|no-slip]
+             [--friction <rate>] [--gravity <g>] [--viscosity <coefficient>]
+             [--back-pressure <p>]
+             [--no-density-flux] [--staggered]
+             [--finite-volume] [--acoustic] [--init-csv <path>]
+             [--dump-csv <path>] [--physical-units] [--blast-probe <density>]
+             [--diff-states <a.csv> <b.csv>]
+             [--block-angle <degrees>] [--blockage <ratio>] [--obstacle-size <cells>]
+             [--checkerboard] [--circulation] [--no-obstacle] [--obstacle-svg <path>]
+             [--obstacle <mask.png>] [--fill-enclosed] [--record <dir>] [--record-every <n>]
+             [--max-frames <n>] [--gif <path>] [--gif-delay <ms>] [--conservative-source]
+             [--inflow-limit velocity=<v>,pressure=<p>] [--flow-meter <x>,...] [--mass-balance]
+             [--perturb <amplitude>,<frequency>] [--color-max <density>] [--cell-borders]
+             [--legend] [--surface-cp] [--cp-csv <path>] [--obstacle-outline]
+             [--arrows] [--arrow-stride <cells>]
+             [--log <path>] [--json-log <path>] [--force-log <path>] [--thermal-log <path>] [--append]
+             [--steady <threshold>] [--pause-when-steady]
+             [--break-on mach|clamp|energy=<fraction>,...]
+             [--reverse-at <time>]
+             [--symmetry] [--dt-safety <factor>] [--fixed-dt <dt>] [--local-dt]
+             [--relaxation <alpha>]
+             [--max-seconds <n>] [--benchmark] [--max-hold pressure|speed] [--show-tracer all|<n>]
+             [--sampling primitive|conservative]
+             [--zoom <x>,<y>,<width>,<height>,<factor>,<time>] [--zoom-screenshot <path>]
+             [--sponge <width>,<strength>,<velocity>]
+             [--forcing <amplitude>,<wavenumber>,<interval>] [--forcing-seed <n>]
+             [--reference] [--script <path>] [--max-step-allocations <n>]
+             [--hotspot <x>,<y>,<density>,<energy>]...
+             [--hotspot-ring <cx>,<cy>,<radius>,<count>,<density>,<energy>]...
+with --physical-units, hotspots take a pressure in Pa instead of an energy
+in the window Space pauses, S takes one step while paused, D prints the state and R
+restarts from t = 0 (Shift+R clears the max hold)";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    // Print the usage and stop, whatever else was asked for
+    pub help: bool,
+    pub headless: bool,
+    pub steps: usize,
+    // Run until this simulated time instead of for `steps`; the window pauses there
+    pub stop_at: Option<Real>,
+    // Multiplies the automatic dt, in (0, 1]
+    pub dt_safety: Real,
+    // Every step takes exactly this dt
+    pub fixed_dt: Option<Real>,
+    pub screenshot: Option<PathBuf>,
+    // Top of the anchored color mode
+    pub color_max: Option<Real>,
+    // Outline every cell, to see the grid itself
+    pub cell_borders: bool,
+    // Events to run at set sim times, replacing the scenario's
+    pub script: Option<PathBuf>,
+    // Draw a colorbar for the mode and a scale bar, in the window and saved images
+    pub legend: bool,
+    // Outline the obstacle in its pressure coefficient, and write it out at the end
+    pub surface_cp: bool,
+    pub cp_csv: Option<PathBuf>,
+    // Draw the obstacle as just its surface, the field showing through inside
+    pub obstacle_outline: bool,
+    // Draw the velocity as arrows, one every so many cells
+    pub arrows: bool,
+    pub arrow_stride: Option<usize>,
+    // Keep every cell's peak of this field from the start, and draw that
+    pub max_hold: Option<HoldField>,
+    // Start in the tracer view, drawing only this tracer or with None all of them
+    pub show_tracer: Option<Option<usize>>,
+    // Write frames into this directory, one every `record_every` steps
+    pub record_dir: Option<PathBuf>,
+    pub record_every: u64,
+    // Stop recording once this many frames are written
+    pub max_frames: Option<usize>,
+    // An animated GIF of the same frames, each shown for `gif_delay` milliseconds
+    pub gif: Option<PathBuf>,
+    pub gif_delay: u32,
+    // Initial grid state, and where to write the final one
+    pub init_csv: Option<PathBuf>,
+    pub dump_csv: Option<PathBuf>,
+    // Per-step CSV of the step reports
+    pub log: Option<PathBuf>,
+    // The same as one JSON object a line
+    pub json_log: Option<PathBuf>,
+    // Per-step CSV of the drag and lift on the obstacle
+    pub force_log: Option<PathBuf>,
+    // Per-step CSV of the obstacle's temperatures
+    pub thermal_log: Option<PathBuf>,
+    // Initial blasts; empty means the single default spot in the center
+    pub hotspots: Vec<Hotspot>,
+    pub measure_diffusion: bool,
+    pub measure_sound_speed: bool,
+    // Compare two --dump-csv files instead of running
+    pub diff_states: Option<(PathBuf, PathBuf)>,
+    // A built-in preset or a setup loaded from a TOML file; the flags below override it
+    pub scenario: Option<String>,
+    pub scenario_file: Option<PathBuf>,
+    // The default setup at another size, its blast and block following along
+    pub grid_width: Option<usize>,
+    pub grid_height: Option<usize>,
+    // Pixels a cell in the window and in screenshots
+    pub cell_size: Option<u32>,
+    // The jet's emission rate and velocity instead of the scenario's
+    pub source_rate: Option<Real>,
+    pub source_velocity: Option<Real>,
+    pub periodic: bool,
+    // The bottom edge is a mirror plane, for the top half of a symmetric setup
+    pub symmetry: bool,
+    // Each cell steps at its own CFL limit, only for steady states
+    pub local_dt: bool,
+    // Under-relaxation of every update, for local timestepping
+    pub relaxation: Option<Real>,
+    pub no_source: bool,
+    pub conservative_source: bool,
+    pub conduction: Option<Real>,
+    pub solid_heat_capacity: Option<Real>,
+    pub wall: Option<WallKind>,
+    pub friction: Option<Real>,
+    pub viscosity: Option<Real>,
+    // Makes the right edge a pressure outlet at this pressure
+    pub back_pressure: Option<Real>,
+    pub gravity: Option<Real>,
+    pub no_density_flux: bool,
+    // Momentum on cell faces instead of centers
+    pub staggered: bool,
+    // The conservative finite volume update instead of the legacy one
+    pub finite_volume: bool,
+    // The finite volume update linearized about the ambient gas, for clean waves
+    pub acoustic: bool,
+    pub physical_units: bool,
+    pub block_angle: Option<Real>,
+    // The wind tunnel's blockage ratio and cylinder diameter in cells
+    pub blockage: Option<Real>,
+    pub obstacle_size: Option<Real>,
+    // Drop whatever obstacle the scenario has, for validation cases that need an empty box
+    pub no_obstacle: bool,
+    // Add the outline of an SVG path to the obstacle
+    pub obstacle_svg: Option<PathBuf>,
+    // Replace the obstacle with a PNG mask, dark pixels solid
+    pub obstacle_png: Option<PathBuf>,
+    // Make fluid walled in by the obstacle solid
+    pub fill_enclosed: bool,
+    // A sponge layer along the right edge, relaxing toward ambient air at this velocity
+    pub sponge: Option<[Real; 3]>,
+    // Random stirring at this acceleration and wavenumber, new phases every interval, and
+    // the seed they're drawn from
+    pub forcing: Option<[Real; 3]>,
+    pub forcing_seed: Option<u64>,
+    // Throttles the source jet where the inlet gas is already too fast or at too high a pressure
+    pub inflow_limit: Option<InflowLimit>,
+    // What the I key's probe interpolates between cells
+    pub sampling: Sampling,
+    // Continue the logs and the frame numbering of an earlier run instead of starting over
+    pub append: bool,
+    // After the run, simulate this region finer for a while and save it here
+    pub zoom: Option<[Real; 6]>,
+    pub zoom_screenshot: Option<PathBuf>,
+    // Columns to meter the flow through, on the faces to their left
+    pub flow_meter: Vec<usize>,
+    // Check every update's change in mass against what crossed the edges
+    pub mass_balance: bool,
+    // Sinusoidal cross-flow on the source jet
+    pub perturbation: Option<[Real; 2]>,
+    // Headless: track the blast front at this density threshold
+    pub blast_probe: Option<Real>,
+    // Headless: watch for odd-even oscillations in the density
+    pub checkerboard: bool,
+    // Headless: report the circulation around the obstacle at the end
+    pub circulation: bool,
+    // Check every step against the simple reference solver, failing if they disagree
+    pub reference: bool,
+    // Headless: stop with an error once the run has taken this long in wall-clock time
+    pub max_seconds: Option<f64>,
+    // Headless: print how long the steps took in wall-clock time at the end
+    pub benchmark: bool,
+    // Fail when a step past the warmup allocates more than this, in count-allocations builds
+    pub max_step_allocations: Option<u64>,
+    // Report when the relative density change per unit time settles below this, and
+    // with `pause_when_steady` stop there (pause, in the window)
+    pub steady: Option<Real>,
+    pub pause_when_steady: bool,
+    // Stop the run (pause, in the window) the first time each of these happens
+    pub break_on: Vec<BreakCondition>,
+    // Turn the flow around after the step reaching this time, as with the B key
+    pub reverse_at: Option<Real>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            help: false,
+            headless: false,
+            steps: 1000,
+            stop_at: None,
+            dt_safety: 1.0,
+            fixed_dt: None,
+            screenshot: None,
+            color_max: None,
+            cell_borders: false,
+            script: None,
+            legend: false,
+            surface_cp: false,
+            obstacle_outline: false,
+            arrows: false,
+            arrow_stride: None,
+            cp_csv: None,
+            max_hold: None,
+            show_tracer: None,
+            record_dir: None,
+            record_every: 1,
+            max_frames: None,
+            gif: None,
+            gif_delay: DEFAULT_GIF_DELAY,
+            init_csv: None,
+            dump_csv: None,
+            log: None,
+            json_log: None,
+            force_log: None,
+            thermal_log: None,
+            hotspots: Vec::new(),
+            measure_diffusion: false,
+            measure_sound_speed: false,
+            diff_states: None,
+            scenario: None,
+            scenario_file: None,
+            grid_width: None,
+            grid_height: None,
+            cell_size: None,
+            source_rate: None,
+            source_velocity: None,
+            periodic: false,
+            symmetry: false,
+            local_dt: false,
+            relaxation: None,
+            no_source: false,
+            conservative_source: false,
+            conduction: None,
+            solid_heat_capacity: None,
+            wall: None,
+            friction: None,
+            viscosity: None,
+            back_pressure: None,
+            gravity: None,
+            no_density_flux: false,
+            staggered: false,
+            finite_volume: false,
+            acoustic: false,
+            physical_units: false,
+            block_angle: None,
+            blockage: None,
+            obstacle_size: None,
+            no_obstacle: false,
+            obstacle_svg: None,
+            obstacle_png: None,
+            fill_enclosed: false,
+            perturbation: None,
+            inflow_limit: None,
+            flow_meter: Vec::new(),
+            mass_balance: false,
+            zoom: None,
+            zoom_screenshot: None,
+            append: false,
+            sampling: Sampling::Primitive,
+            sponge: None,
+            forcing: None,
+            forcing_seed: None,
+            blast_probe: None,
+            checkerboard: false,
+            circulation: false,
+            reference: false,
+            max_seconds: None,
+            benchmark: false,
+            max_step_allocations: None,
+            steady: None,
+            pause_when_steady: false,
+            break_on: Vec::new(),
+            reverse_at: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--measure-diffusion" => options.measure_diffusion = true,
+                "--measure-sound-speed" => options.measure_sound_speed = true,
+                "--mass-balance" => options.mass_balance = true,
+                "--diff-states" => {
+                    let first = value(&arg, args.next())?;
+                    let second = value(&arg, args.next())?;
+                    options.diff_states = Some((PathBuf::from(first), PathBuf::from(second)));
+                }
+                "--periodic" => options.periodic = true,
+                "--no-source" => options.no_source = true,
+                "--conservative-source" => options.conservative_source = true,
+                "--no-density-flux" => options.no_density_flux = true,
+                // Carried the mass with the flow in the legacy scheme, which always does now
+                "--advect" => {}
+                // Asked for the CFL step in the legacy scheme, which always takes it now
+                "--adaptive-dt" => {}
+                "--staggered" => options.staggered = true,
+                "--finite-volume" => options.finite_volume = true,
+                "--acoustic" => options.acoustic = true,
+                "--physical-units" => options.physical_units = true,
+                "--no-obstacle" => options.no_obstacle = true,
+                "--fill-enclosed" => options.fill_enclosed = true,
+                "--checkerboard" => options.checkerboard = true,
+                "--circulation" => options.circulation = true,
+                "--reference" => options.reference = true,
+                "--symmetry" => options.symmetry = true,
+                "--local-dt" => options.local_dt = true,
+                "--relaxation" => {
+                    let alpha: Real = parse_value(&arg, args.next())?;
+                    if alpha.is_nan() || alpha <= 0.0 || alpha > 1.0 {
+                        return Err(format!("`{}` expects a factor in (0, 1]", arg));
+                    }
+                    options.relaxation = Some(alpha);
+                }
+                "--pause-when-steady" => options.pause_when_steady = true,
+                "--reverse-at" => options.reverse_at = Some(parse_value(&arg, args.next())?),
+                "--break-on" => {
+                    let list = value(&arg, args.next())?;
+                    for name in list.split(',') {
+                        let condition = BreakCondition::parse(name.trim()).ok_or_else(|| {
+                            format!(
+                                "unknown break condition `{}`, expected mach, clamp or energy=<fraction>",
+                                name
+                            )
+                        })?;
+                        options.break_on.push(condition);
+                    }
+                }
+                "--steady" => options.steady = Some(parse_value(&arg, args.next())?),
+                "--cell-borders" => options.cell_borders = true,
+                "--legend" => options.legend = true,
+                "--surface-cp" => options.surface_cp = true,
+                "--obstacle-outline" => options.obstacle_outline = true,
+                "--arrows" => options.arrows = true,
+                "--arrow-stride" => {
+                    let stride: usize = parse_value(&arg, args.next())?;
+                    if stride == 0 {
+                        return Err(format!("`{}` expects at least 1 cell", arg));
+                    }
+                    options.arrow_stride = Some(stride);
+                }
+                "--cp-csv" => options.cp_csv = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--block-angle" => options.block_angle = Some(parse_value(&arg, args.next())?),
+                "--blockage" => options.blockage = Some(parse_value(&arg, args.next())?),
+                "--obstacle-size" => options.obstacle_size = Some(parse_value(&arg, args.next())?),
+                "--blast-probe" => options.blast_probe = Some(parse_value(&arg, args.next())?),
+                "--conduction" => options.conduction = Some(parse_value(&arg, args.next())?),
+                "--solid-heat-capacity" => {
+                    options.solid_heat_capacity = Some(parse_value(&arg, args.next())?)
+                }
+                "--friction" => options.friction = Some(parse_value(&arg, args.next())?),
+                "--viscosity" => options.viscosity = Some(parse_value(&arg, args.next())?),
+                "--grid-width" => options.grid_width = Some(parse_value(&arg, args.next())?),
+                "--grid-height" => options.grid_height = Some(parse_value(&arg, args.next())?),
+                "--cell-size" => {
+                    let size: u32 = parse_value(&arg, args.next())?;
+                    if size == 0 {
+                        return Err(format!("`{}` expects at least 1 pixel", arg));
+                    }
+                    options.cell_size = Some(size);
+                }
+                "--source-rate" => options.source_rate = Some(parse_value(&arg, args.next())?),
+                "--source-velocity" => {
+                    options.source_velocity = Some(parse_value(&arg, args.next())?)
+                }
+                "--back-pressure" => options.back_pressure = Some(parse_value(&arg, args.next())?),
+                "--gravity" => options.gravity = Some(parse_value(&arg, args.next())?),
+                "--wall" => {
+                    let name = value(&arg, args.next())?;
+                    let wall = WallKind::parse(&name).ok_or_else(|| {
+                        format!("unknown wall kind `{}`, expected slip or no-slip", name)
+                    })?;
+                    options.wall = Some(wall);
+                }
+                "--sampling" => {
+                    let name = value(&arg, args.next())?;
+                    options.sampling = Sampling::parse(&name).ok_or_else(|| {
+                        format!(
+                            "unknown sampling `{}`, expected primitive or conservative",
+                            name
+                        )
+                    })?;
+                }
+                "--max-hold" => {
+                    let name = value(&arg, args.next())?;
+                    let field = HoldField::parse(&name).ok_or_else(|| {
+                        format!(
+                            "unknown max hold field `{}`, expected pressure or speed",
+                            name
+                        )
+                    })?;
+                    options.max_hold = Some(field);
+                }
+                "--show-tracer" => {
+                    let name = value(&arg, args.next())?;
+                    options.show_tracer = Some(match name.as_str() {
+                        "all" => None,
+                        _ => Some(name.parse().map_err(|_| {
+                            format!("bad tracer `{}`, expected all or an index", name)
+                        })?),
+                    });
+                }
+                "--obstacle-svg" => {
+                    options.obstacle_svg = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--obstacle" => {
+                    options.obstacle_png = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--init-csv" => options.init_csv = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--dump-csv" => options.dump_csv = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--script" => options.script = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--scenario" => options.scenario = Some(value(&arg, args.next())?),
+                "--scenario-file" => {
+                    options.scenario_file = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--steps" => options.steps = parse_value(&arg, args.next())?,
+                "--stop-at" => options.stop_at = Some(parse_value(&arg, args.next())?),
+                "--max-seconds" => options.max_seconds = Some(parse_value(&arg, args.next())?),
+                "--benchmark" => options.benchmark = true,
+                "--max-step-allocations" => {
+                    if !cfg!(feature = "count-allocations") {
+                        return Err(format!(
+                            "`{}` needs a build with the count-allocations feature",
+                            arg
+                        ));
+                    }
+                    options.max_step_allocations = Some(parse_value(&arg, args.next())?);
+                }
+                "--dt-safety" => {
+                    let safety: Real = parse_value(&arg, args.next())?;
+                    if safety.is_nan() || safety <= 0.0 || safety > 1.0 {
+                        return Err(format!("`{}` expects a factor in (0, 1]", arg));
+                    }
+                    options.dt_safety = safety;
+                }
+                "--fixed-dt" => {
+                    let dt: Real = parse_value(&arg, args.next())?;
+                    if !(dt > 0.0 && dt.is_finite()) {
+                        return Err(format!("`{}` expects a positive time step", arg));
+                    }
+                    options.fixed_dt = Some(dt);
+                }
+                "--screenshot" => {
+                    options.screenshot = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--log" => options.log = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--json-log" => options.json_log = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--force-log" => options.force_log = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--thermal-log" => {
+                    options.thermal_log = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--record" => options.record_dir = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--color-max" => options.color_max = Some(parse_value(&arg, args.next())?),
+                "--record-every" => options.record_every = parse_value(&arg, args.next())?,
+                "--max-frames" => {
+                    let frames: usize = parse_value(&arg, args.next())?;
+                    if frames == 0 {
+                        return Err(format!("`{}` expects at least 1 frame", arg));
+                    }
+                    options.max_frames = Some(frames);
+                }
+                "--gif" => options.gif = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--gif-delay" => options.gif_delay = parse_value(&arg, args.next())?,
+                "--sponge" => options.sponge = Some(parse_list(&arg, args.next())?),
+                "--forcing" => options.forcing = Some(parse_list(&arg, args.next())?),
+                "--forcing-seed" => options.forcing_seed = Some(parse_value(&arg, args.next())?),
+                "--append" => options.append = true,
+                "--zoom" => options.zoom = Some(parse_list(&arg, args.next())?),
+                "--zoom-screenshot" => {
+                    options.zoom_screenshot = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--flow-meter" => {
+                    let raw = value(&arg, args.next())?;
+                    options.flow_meter = raw
+                        .split(',')
+                        .map(|part| part.trim().parse::<usize>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| {
+                            format!(
+                                "`--flow-meter` expects comma separated columns, got `{}`",
+                                raw
+                            )
+                        })?;
+                }
+                "--inflow-limit" => {
+                    let raw = value(&arg, args.next())?;
+                    options.inflow_limit = Some(InflowLimit::parse(&raw).ok_or_else(|| {
+                        format!(
+                            "`--inflow-limit` expects velocity=<v>,pressure=<p> or either one, got `{}`",
+                            raw
+                        )
+                    })?);
+                }
+                "--perturb" => options.perturbation = Some(parse_list(&arg, args.next())?),
+                "--hotspot" => {
+                    let [x, y, density, energy] = parse_list(&arg, args.next())?;
+                    options.hotspots.push(Hotspot {
+                        x: x as usize,
+                        y: y as usize,
+                        density,
+                        energy,
+                    });
+                }
+                "--hotspot-ring" => {
+                    let [cx, cy, radius, count, density, energy] = parse_list(&arg, args.next())?;
+                    options.hotspots.extend(ring_hotspots(
+                        (cx, cy),
+                        radius,
+                        count as usize,
+                        density,
+                        energy,
+                    ));
+                }
+                "-h" | "--help" => {
+                    options.help = true;
+                    return Ok(options);
+                }
+                _ => return Err(format!("unknown argument `{}`\n{}", arg, USAGE)),
+            }
+        }
+
+        if options.fixed_dt.is_some() && options.dt_safety != 1.0 {
+            return Err("--fixed-dt and --dt-safety can't be used together".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("`{}` expects a value\n{}", flag, USAGE))
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, raw: Option<String>) -> Result<T, String> {
+    let raw = value(flag, raw)?;
+    raw.parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", raw, flag))
+}
+
+// Comma separated numbers, e.g. `--hotspot 40,30,10,1000`
+fn parse_list<const N: usize>(flag: &str, raw: Option<String>) -> Result<[Real; N], String> {
+    let raw = value(flag, raw)?;
+    let numbers = raw
+        .split(',')
+        .map(|part| part.trim().parse::<Real>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|numbers| numbers.len() == N && numbers.iter().all(|n| *n >= 0.0))
+        .ok_or_else(|| {
+            format!(
+                "`{}` expects {} non-negative comma separated numbers, got `{}`",
+                flag, N, raw
+            )
+        })?;
+    Ok(numbers.try_into().unwrap())
+}
@@ -0,0 +1,30 @@
+use std::error::Error;
+use std::fmt;
+
+// An error message meant for the user. Debug prints it verbatim because that is what
+// gets shown when `main` returns an Err.
+pub struct AppError(pub String);
+
+impl fmt::Debug for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for AppError {}
+
+pub trait Context<T> {
+    fn context(self, message: &str) -> Result<T, AppError>;
+}
+
+impl<T, E: fmt::Display> Context<T> for Result<T, E> {
+    fn context(self, message: &str) -> Result<T, AppError> {
+        self.map_err(|e| AppError(format!("{}: {}", message, e)))
+    }
+}
@@ -0,0 +1,115 @@
+use crate::consts::PI;
+use crate::Real;
+use serde::{Deserialize, Serialize};
+
+// Random stirring at large scales, for keeping turbulence going in a periodic box without a
+// jet: a push along a handful of Fourier modes of the box, all the same size of `wavenumber`
+// waves across it (give or take half a wave), each across its own wave vector so it only
+// swirls the gas instead of squeezing it. The phases are drawn again every `interval` of
+// sim time, blending from one set into the next, from a hash of `seed` so a run repeats
+// exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Forcing {
+    pub amplitude: Real, // Acceleration, about the rms of each component
+    pub wavenumber: Real,
+    pub interval: Real,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl Forcing {
+    pub fn problem(&self) -> Option<String> {
+        if !(self.amplitude.is_finite() && self.amplitude >= 0.0) {
+            return Some(format!(
+                "the forcing amplitude has to be 0 or more, got {}",
+                self.amplitude
+            ));
+        }
+        if !(self.interval.is_finite() && self.interval > 0.0) {
+            return Some(format!(
+                "the forcing interval has to be positive, got {}",
+                self.interval
+            ));
+        }
+        if modes(self.wavenumber).is_empty() {
+            return Some(format!(
+                "no box modes have a wavenumber within half of {}",
+                self.wavenumber
+            ));
+        }
+        None
+    }
+
+    // The acceleration at every cell center at time t
+    pub fn field(&self, width: usize, height: usize, t: Real) -> Vec<Vec<(Real, Real)>> {
+        let modes = modes(self.wavenumber);
+        let s = (t / self.interval).max(0.0);
+        let set = s.floor() as u64;
+        let blend = s - s.floor();
+        // Each mode's phase in the set now and the next one
+        let phases: Vec<(Real, Real)> = (0..modes.len())
+            .map(|m| {
+                let phase = |set: u64| 2.0 * PI * unit(self.seed, set, m as u64);
+                (phase(set), phase(set + 1))
+            })
+            .collect();
+        let scale = self.amplitude * (2.0 / modes.len() as Real).sqrt();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let (px, py) = (
+                            (x as Real + 0.5) / width as Real,
+                            (y as Real + 0.5) / height as Real,
+                        );
+                        let (mut ax, mut ay) = (0.0, 0.0);
+                        for (&(kx, ky), &(now, next)) in modes.iter().zip(&phases) {
+                            let (kx, ky) = (kx as Real, ky as Real);
+                            let angle = 2.0 * PI * (kx * px + ky * py);
+                            let strength =
+                                (1.0 - blend) * (angle + now).cos() + blend * (angle + next).cos();
+                            let k = kx.hypot(ky);
+                            ax -= strength * ky / k;
+                            ay += strength * kx / k;
+                        }
+                        (scale * ax, scale * ay)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// The wave vectors, in whole waves across the box, within half a wave of `wavenumber`.
+// One of each pair k and -k, they're the same mode.
+fn modes(wavenumber: Real) -> Vec<(i64, i64)> {
+    if !wavenumber.is_finite() || wavenumber <= 0.0 {
+        return Vec::new();
+    }
+    let reach = (wavenumber + 0.5).ceil() as i64;
+    let mut modes = Vec::new();
+    for ky in 0..=reach {
+        for kx in -reach..=reach {
+            if ky == 0 && kx <= 0 {
+                continue;
+            }
+            let k = (kx as Real).hypot(ky as Real);
+            if (k - wavenumber).abs() <= 0.5 {
+                modes.push((kx, ky));
+            }
+        }
+    }
+    modes
+}
+
+// A number in [0, 1) that only depends on its arguments, from splitmix64
+fn unit(seed: u64, set: u64, mode: u64) -> Real {
+    let mut z = seed
+        .wrapping_add(set.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(mode.wrapping_mul(0xbf58_476d_1ce4_e5b9))
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as Real / (1u64 << 53) as Real
+}
@@ -0,0 +1,149 @@
+use crate::Real;
+
+// Bounds-checked access to the row-major grids (cells, solid masks, coverage fractions),
+// indexed (x, y) like everything else. `grid[y][x]` panics on a bad index; these return
+// None instead, which leaves the caller to decide what an edge means.
+pub trait Grid<T> {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn at(&self, x: usize, y: usize) -> Option<&T>;
+    fn at_mut(&mut self, x: usize, y: usize) -> Option<&mut T>;
+
+    // The in-bounds 4-neighbors of (x, y), wrapping around the edges on a torus
+    fn neighbors(&self, x: usize, y: usize, periodic: bool) -> Neighbors {
+        Neighbors {
+            x,
+            y,
+            width: self.width(),
+            height: self.height(),
+            periodic,
+            next: 0,
+        }
+    }
+
+    // All four neighbors of (x, y) for a flux stencil, or None if (x, y) isn't on the grid
+    // or sits on an edge with a neighbor missing
+    fn stencil(&self, x: usize, y: usize, periodic: bool) -> Option<Stencil> {
+        let (width, height) = (self.width(), self.height());
+        if x >= width || y >= height {
+            return None;
+        }
+        if !periodic && (x == 0 || y == 0 || x == width - 1 || y == height - 1) {
+            return None;
+        }
+        Some(Stencil {
+            left: (x + width - 1) % width,
+            right: (x + 1) % width,
+            up: (y + height - 1) % height,
+            down: (y + 1) % height,
+        })
+    }
+}
+
+impl<T> Grid<T> for [Vec<T>] {
+    fn width(&self) -> usize {
+        self.first().map_or(0, Vec::len)
+    }
+
+    fn height(&self) -> usize {
+        self.len()
+    }
+
+    fn at(&self, x: usize, y: usize) -> Option<&T> {
+        self.get(y)?.get(x)
+    }
+
+    fn at_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        self.get_mut(y)?.get_mut(x)
+    }
+}
+
+// Columns either side and rows above and below a cell
+#[derive(Debug, Clone, Copy)]
+pub struct Stencil {
+    pub left: usize,
+    pub right: usize,
+    pub up: usize,
+    pub down: usize,
+}
+
+pub struct Neighbors {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    periodic: bool,
+    next: usize, // Index into OFFSETS
+}
+
+const OFFSETS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+impl Iterator for Neighbors {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        while let Some(&(dx, dy)) = OFFSETS.get(self.next) {
+            self.next += 1;
+            let nx = self.x as isize + dx;
+            let ny = self.y as isize + dy;
+            let (width, height) = (self.width as isize, self.height as isize);
+            if self.periodic {
+                return Some((
+                    nx.rem_euclid(width) as usize,
+                    ny.rem_euclid(height) as usize,
+                ));
+            }
+            if nx >= 0 && ny >= 0 && nx < width && ny < height {
+                return Some((nx as usize, ny as usize));
+            }
+        }
+        None
+    }
+}
+
+// For interpolating at `f` along an axis of `n` cells, in cell indices so that cell i's
+// center is at f = i: the cells on either side and the weight of the second. Past the
+// outer centers both are the edge cell, unless the axis wraps around.
+pub fn interpolation_cells(f: Real, n: usize, periodic: bool) -> (usize, usize, Real) {
+    let f = if periodic {
+        f.rem_euclid(n as Real)
+    } else {
+        f.clamp(0.0, (n - 1) as Real)
+    };
+    let low = f.floor();
+    // Rounding can put a wrapped position right on n
+    let (low_cell, high_cell) = if periodic {
+        let cell = low as usize % n;
+        (cell, (cell + 1) % n)
+    } else {
+        let cell = low as usize;
+        (cell, (cell + 1).min(n - 1))
+    };
+    (low_cell, high_cell, f - low)
+}
+
+// Every 4-connected region of the cells where `open` is set, each as its cells' (x, y)
+pub fn connected_regions(open: &[Vec<bool>], periodic: bool) -> Vec<Vec<(usize, usize)>> {
+    let mut seen = vec![vec![false; open.width()]; open.height()];
+    let mut regions = Vec::new();
+    for (y, row) in open.iter().enumerate() {
+        for (x, _) in row.iter().enumerate().filter(|(_, &o)| o) {
+            if seen[y][x] {
+                continue;
+            }
+            seen[y][x] = true;
+            let (mut region, mut stack) = (Vec::new(), vec![(x, y)]);
+            while let Some((cx, cy)) = stack.pop() {
+                region.push((cx, cy));
+                for (nx, ny) in open.neighbors(cx, cy, periodic) {
+                    if open[ny][nx] && !seen[ny][nx] {
+                        seen[ny][nx] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions
+}
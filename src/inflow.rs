@@ -0,0 +1,149 @@
+use crate::consts::PI;
+use crate::Real;
+use serde::{Deserialize, Serialize};
+
+// A factor on the jet's inflow that changes with sim time, for gusts and other unsteady
+// inflows. Only a function of t like the cross-flow, so runs stay reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum InflowSchedule {
+    // `before` until `time`, `after` from then on
+    Step {
+        time: Real,
+        before: Real,
+        after: Real,
+    },
+    // `from` until `start`, straight up (or down) to `to` at `end`, then held there
+    Ramp {
+        start: Real,
+        end: Real,
+        from: Real,
+        to: Real,
+    },
+    // Swinging around `mean`, `frequency` in cycles per unit of sim time
+    Sinusoid {
+        mean: Real,
+        amplitude: Real,
+        frequency: Real,
+    },
+}
+
+impl InflowSchedule {
+    pub fn factor(&self, t: Real) -> Real {
+        match *self {
+            InflowSchedule::Step {
+                time,
+                before,
+                after,
+            } => {
+                if t < time {
+                    before
+                } else {
+                    after
+                }
+            }
+            InflowSchedule::Ramp {
+                start,
+                end,
+                from,
+                to,
+            } => {
+                if t <= start {
+                    from
+                } else if t >= end {
+                    to
+                } else {
+                    from + (to - from) * (t - start) / (end - start)
+                }
+            }
+            InflowSchedule::Sinusoid {
+                mean,
+                amplitude,
+                frequency,
+            } => mean + amplitude * (2.0 * PI * frequency * t).sin(),
+        }
+    }
+
+    // Why the schedule can't be used, if it can't
+    pub fn problem(&self) -> Option<String> {
+        let numbers: &[Real] = match self {
+            InflowSchedule::Step {
+                time,
+                before,
+                after,
+            } => &[*time, *before, *after],
+            InflowSchedule::Ramp {
+                start,
+                end,
+                from,
+                to,
+            } => {
+                if end <= start {
+                    return Some(format!(
+                        "a ramp has to end after it starts, got {} to {}",
+                        start, end
+                    ));
+                }
+                &[*start, *end, *from, *to]
+            }
+            InflowSchedule::Sinusoid {
+                mean,
+                amplitude,
+                frequency,
+            } => &[*mean, *amplitude, *frequency],
+        };
+        numbers
+            .iter()
+            .any(|n| !n.is_finite())
+            .then(|| "an inflow schedule's numbers all have to be finite".to_string())
+    }
+}
+
+// A pressure-limited inlet: the jet backs off in the cells where the gas is already moving
+// faster than `velocity` or pushing back harder than `pressure`, so it can't keep pumping
+// energy into a flow that is running away. Full flow up to a limit, none at twice it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InflowLimit {
+    pub velocity: Option<Real>,
+    pub pressure: Option<Real>,
+}
+
+impl InflowLimit {
+    // From `velocity=<v>,pressure=<p>`, either one or both
+    pub fn parse(raw: &str) -> Option<InflowLimit> {
+        let mut limit = InflowLimit::default();
+        for part in raw.split(',') {
+            let (name, value) = part.trim().split_once('=')?;
+            let value = value.trim().parse::<Real>().ok()?;
+            match name.trim() {
+                "velocity" => limit.velocity = Some(value),
+                "pressure" => limit.pressure = Some(value),
+                _ => return None,
+            }
+        }
+        Some(limit)
+    }
+
+    // How much of its usual inflow the jet puts into a cell with this speed and pressure,
+    // 0 to 1
+    pub fn factor(&self, speed: Real, pressure: Real) -> Real {
+        let backoff = |value: Real, limit: Real| (2.0 - value / limit).clamp(0.0, 1.0);
+        let mut factor: Real = 1.0;
+        if let Some(limit) = self.velocity {
+            factor = factor.min(backoff(speed, limit));
+        }
+        if let Some(limit) = self.pressure {
+            factor = factor.min(backoff(pressure, limit));
+        }
+        factor
+    }
+
+    pub fn problem(&self) -> Option<String> {
+        [self.velocity, self.pressure]
+            .iter()
+            .flatten()
+            .any(|limit| !(limit.is_finite() && *limit > 0.0))
+            .then(|| "inflow limits have to be positive".to_string())
+    }
+}
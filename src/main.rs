@@ -1,226 +1,3132 @@
+mod acoustic;
+mod alloc_count;
+mod cli;
+mod diagnostics;
+mod error;
+mod finite_volume;
+mod font;
+mod forcing;
+mod grid;
+mod inflow;
+mod mask;
+mod reference;
+mod render;
+mod riemann;
+mod scenario;
+mod script;
+mod shape;
+mod snapshot;
+mod spectrum;
+mod svg;
+mod tracer;
+mod zoom;
+
+use alloc_count::AllocationCheck;
+use cli::Options;
+use diagnostics::{
+    BlastProbe, BreakCondition, Breakpoints, CheckerboardMonitor, FlowMeter, ForceLog, HoldField,
+    JsonLog, Logs, MassBalance, MaxHold, StallWatch, SteadyState, StepLog, ThermalLog, WakeSignal,
+};
+use error::{AppError, Context};
+use finite_volume::Face;
+use forcing::Forcing;
+use grid::{interpolation_cells, Grid, Stencil};
+use inflow::{InflowLimit, InflowSchedule};
+use rayon::prelude::*;
+use reference::ReferenceCheck;
+use render::{
+    cell_under, draw_brush, draw_zoom, histogram_label, render_grid, save_canvas_png,
+    save_grid_png, window_to_cell, HistogramField, Recorder, RenderMode, View,
+    DEFAULT_ARROW_STRIDE, DEFAULT_COLOR_MAX,
+};
+use scenario::{Scenario, SelfTest, Sponge};
+use script::Script;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::Texture;
 use sdl2::Sdl;
+use serde::{Deserialize, Serialize};
+use shape::{norm_to_cell, Shape};
+use std::error::Error;
+use std::ops::{Add, Div, Mul, Range, Sub};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::Instant;
+use tracer::{Material, TracerField};
+use zoom::Zoom;
+
+// The float the sim works in. With the f32 feature it's single precision, halving the
+// memory the grids take and the traffic through them on big grids.
+#[cfg(not(feature = "f32"))]
+pub type Real = f64;
+#[cfg(feature = "f32")]
+pub type Real = f32;
+#[cfg(feature = "f32")]
+use std::f32::consts;
+#[cfg(not(feature = "f32"))]
+use std::f64::consts;
+
+const AIR_DENSITY: Real = 1.225; // kg/m^3
+const GAMMA_AIR: Real = 1.4; // Adiabatic index for air
+const ATMOSPHERIC_PRESSURE: Real = 101325.0; // Pa
+const AIR_ENERGY: Real = 1000.0; // Initial energy (example)
+
+const CELL_SIZE: u32 = 10; // Pixels per grid cell, unless --cell-size says otherwise
+
+const DEFAULT_DT: Real = 1.0; // The step every update used to assume implicitly
+const DT_SAFETY_STEP: Real = 1.25; // Factor the [ and ] keys change the dt safety by
+
+// The . key starts slow motion at this many seconds per step and doubles it from there,
+// the , key halves it back down and drops out of slow motion below the start
+const SLOW_MOTION_START: f64 = 0.05;
+const SLOW_MOTION_STEP: f64 = 2.0;
+
+// Shift with the same keys tunes the artificial viscosity: > turns it on at this coefficient
+// and raises it from there, < lowers it and turns it off below the start
+const VISCOSITY_START: Real = 0.1;
+const VISCOSITY_STEP: Real = 1.25;
+
+// Most of the velocity difference across a face the artificial viscosity evens out in one
+// step. With four faces a cell is then never pushed past the average of its neighbors.
+const MAX_VISCOUS_SHARE: Real = 0.125;
+
+// Meters across a grid cell in either direction. The solver takes cells to be one unit
+// across, so this only places physical coordinates on the grid.
+const CELL_LENGTH: Real = 1.0;
+
+// The P key's push: outward flow at this fraction of the local sound speed at the cursor,
+// falling off to nothing at POKE_RADIUS cells, or the brush radius if that's bigger
+const POKE_MACH: Real = 0.3;
+const POKE_RADIUS: Real = 3.0;
+
+// Largest brush the mouse wheel goes up to, in cells. 0 is a single cell.
+const MAX_BRUSH_RADIUS: usize = 20;
+
+// The Q key's zoom: how much finer, and for how many of the coarse run's own steps
+const ZOOM_FACTOR: usize = 4;
+const ZOOM_STEPS: Real = 50.0;
+
+// Side of the default block, as a fraction of the grid height
+const BLOCK_SIZE: Real = 1.0 / 3.0;
+
+// Cells of fluid kept around the obstacle's bounding box when integrating its circulation
+const CIRCULATION_MARGIN: usize = 3;
+
+const WINDOW_TITLE: &str = "Euler Fluid Simulation with Airfoil";
+const SCENARIO_EXPORT_PATH: &str = "scenario.toml"; // Written by the E key
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    density: Real,
+    momentum_x: Real,
+    momentum_y: Real,
+    energy: Real,
+}
+
+impl Cell {
+    fn zero() -> Cell {
+        Cell {
+            density: 0.0,
+            momentum_x: 0.0,
+            momentum_y: 0.0,
+            energy: 0.0,
+        }
+    }
+
+    // Still air at the reference density and energy
+    fn ambient() -> Cell {
+        Cell {
+            density: AIR_DENSITY,
+            momentum_x: 0.0,
+            momentum_y: 0.0,
+            energy: AIR_ENERGY,
+        }
+    }
+
+    // The conserved state for a density, velocity and pressure
+    fn from_primitives(density: Real, vx: Real, vy: Real, pressure: Real, gamma: Real) -> Cell {
+        Cell {
+            density,
+            momentum_x: density * vx,
+            momentum_y: density * vy,
+            energy: energy_from_primitives(density, vx, vy, pressure, gamma),
+        }
+    }
+}
+
+// Total energy per volume: internal p / (gamma - 1) plus kinetic rho v^2 / 2
+fn energy_from_primitives(density: Real, vx: Real, vy: Real, pressure: Real, gamma: Real) -> Real {
+    pressure / (gamma - 1.0) + 0.5 * density * (vx * vx + vy * vy)
+}
+
+// The inverse of Cell::from_primitives: (density, vx, vy, pressure)
+fn primitives_from_cell(cell: &Cell, gamma: Real) -> (Real, Real, Real, Real) {
+    let (vx, vy) = velocity(cell);
+    (cell.density, vx, vy, calculate_pressure(cell, gamma))
+}
+
+// The state at a point, as returned by Simulation::sample_at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Primitives {
+    pub density: Real,
+    pub velocity_x: Real,
+    pub velocity_y: Real,
+    pub pressure: Real,
+}
+
+// What sample_at interpolates between the cells. Primitives blend the density, velocity and
+// pressure themselves; conserved variables blend the mass, momentum and energy and take the
+// primitives of the blend, which keeps a point between the two sides of a shock on the
+// Rankine-Hugoniot line between them instead of making up a pressure that fits neither.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Sampling {
+    #[default]
+    Primitive,
+    Conservative,
+}
+
+impl Sampling {
+    pub fn parse(name: &str) -> Option<Sampling> {
+        match name {
+            "primitive" => Some(Sampling::Primitive),
+            "conservative" => Some(Sampling::Conservative),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sampling::Primitive => "primitive",
+            Sampling::Conservative => "conservative",
+        }
+    }
+}
+
+// How the numbers in a scenario are meant. Legacy keeps the original unitless values
+// (ambient energy 1000, a source that adds density and sets momentum). Physical starts
+// from air at AIR_DENSITY and ATMOSPHERIC_PRESSURE, and the source holds the inflow cells
+// at that state moving at `velocity` m/s.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Units {
+    Legacy,
+    Physical,
+}
+
+impl Units {
+    fn ambient(self, gamma: Real) -> Cell {
+        match self {
+            Units::Legacy => Cell::ambient(),
+            Units::Physical => {
+                Cell::from_primitives(AIR_DENSITY, 0.0, 0.0, ATMOSPHERIC_PRESSURE, gamma)
+            }
+        }
+    }
+}
+
+// Component-wise arithmetic so the update code can treat a cell as a state vector
+
+impl Add for Cell {
+    type Output = Cell;
+
+    fn add(self, other: Cell) -> Cell {
+        Cell {
+            density: self.density + other.density,
+            momentum_x: self.momentum_x + other.momentum_x,
+            momentum_y: self.momentum_y + other.momentum_y,
+            energy: self.energy + other.energy,
+        }
+    }
+}
+
+impl Sub for Cell {
+    type Output = Cell;
+
+    fn sub(self, other: Cell) -> Cell {
+        Cell {
+            density: self.density - other.density,
+            momentum_x: self.momentum_x - other.momentum_x,
+            momentum_y: self.momentum_y - other.momentum_y,
+            energy: self.energy - other.energy,
+        }
+    }
+}
+
+impl Mul<Real> for Cell {
+    type Output = Cell;
+
+    fn mul(self, factor: Real) -> Cell {
+        Cell {
+            density: self.density * factor,
+            momentum_x: self.momentum_x * factor,
+            momentum_y: self.momentum_y * factor,
+            energy: self.energy * factor,
+        }
+    }
+}
+
+impl Div<Real> for Cell {
+    type Output = Cell;
+
+    fn div(self, divisor: Real) -> Cell {
+        Cell {
+            density: self.density / divisor,
+            momentum_x: self.momentum_x / divisor,
+            momentum_y: self.momentum_y / divisor,
+            energy: self.energy / divisor,
+        }
+    }
+}
+
+// Picks the next unused <prefix>_NNNN.<extension> in the working directory
+fn next_free_path(prefix: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("{}_{:04}.{}", prefix, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// A single cell set to a given density and energy at t = 0
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hotspot {
+    pub x: usize,
+    pub y: usize,
+    pub density: Real,
+    pub energy: Real,
+}
+
+// The original setup: a high-pressure region in the center for initial movement
+fn default_hotspots(width: usize, height: usize) -> Vec<Hotspot> {
+    vec![Hotspot {
+        x: width / 2,
+        y: height / 2,
+        density: 10.0,
+        energy: 1000.0, // High energy in the center
+    }]
+}
+
+// `count` spots evenly spaced on a circle, handy for watching several blasts merge
+pub fn ring_hotspots(
+    center: (Real, Real),
+    radius: Real,
+    count: usize,
+    density: Real,
+    energy: Real,
+) -> Vec<Hotspot> {
+    (0..count)
+        .map(|i| {
+            let angle = 2.0 * consts::PI * i as Real / count as Real;
+            Hotspot {
+                x: (center.0 + radius * angle.cos()).round().max(0.0) as usize,
+                y: (center.1 + radius * angle.sin()).round().max(0.0) as usize,
+                density,
+                energy,
+            }
+        })
+        .collect()
+}
+
+fn seed_hotspots(grid: &mut [Vec<Cell>], spots: &[Hotspot]) {
+    for spot in spots {
+        // Scenario::build rejects spots off the grid, anything else is skipped
+        if let Some(cell) = grid.at_mut(spot.x, spot.y) {
+            cell.density = spot.density;
+            cell.energy = spot.energy;
+        }
+    }
+}
+
+fn initialize_grid(
+    width: usize,
+    height: usize,
+    ambient: Cell,
+    spots: &[Hotspot],
+) -> Vec<Vec<Cell>> {
+    let mut grid = vec![vec![ambient; width]; height];
+
+    seed_hotspots(&mut grid, spots);
+
+    grid
+}
+
+// The ambient gas with every cell moving at `velocity(px, py)` of its center, in cells. The
+// density and pressure stay the ambient ones, so the energy is the ambient internal energy
+// plus whatever kinetic energy the motion brings.
+fn initialize_with_velocity(
+    width: usize,
+    height: usize,
+    ambient: Cell,
+    gamma: Real,
+    velocity: impl Fn(Real, Real) -> (Real, Real),
+) -> Vec<Vec<Cell>> {
+    let pressure = calculate_pressure(&ambient, gamma);
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let (vx, vy) = velocity(x as Real + 0.5, y as Real + 0.5);
+                    Cell::from_primitives(ambient.density, vx, vy, pressure, gamma)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Gas at rest in hydrostatic balance under `gravity`, which pulls toward +y (down the
+// screen). Row y is at temperature `temperatures[y]` and the top row at `base_pressure`.
+// Neighboring rows satisfy p[y + 1] - p[y] = gravity * (rho[y] + rho[y + 1]) / 2: the pressure
+// step across their shared face carries the weight of the gas around it. Every row has to be
+// warmer than gravity / 2, or the density would go negative.
+fn initialize_stratified(
+    width: usize,
+    height: usize,
+    gravity: Real,
+    base_pressure: Real,
+    temperatures: &[Real],
+    gamma: Real,
+) -> Vec<Vec<Cell>> {
+    let mut density = base_pressure / temperatures[0];
+    (0..height)
+        .map(|y| {
+            if y > 0 {
+                density *=
+                    (temperatures[y - 1] + gravity / 2.0) / (temperatures[y] - gravity / 2.0);
+            }
+            let pressure = density * temperatures[y];
+            vec![Cell::from_primitives(density, 0.0, 0.0, pressure, gamma); width]
+        })
+        .collect()
+}
+
+// Per unit volume, like `energy`; what's left of `energy` after it is the internal part
+fn kinetic_energy(cell: &Cell) -> Real {
+    (cell.momentum_x.powi(2) + cell.momentum_y.powi(2)) / (2.0 * cell.density)
+}
+
+fn calculate_pressure(cell: &Cell, gamma: Real) -> Real {
+    (gamma - 1.0) * (cell.energy - kinetic_energy(cell))
+}
+
+// Ideal gas temperature in units where the gas constant is 1
+fn temperature(cell: &Cell, gamma: Real) -> Real {
+    calculate_pressure(cell, gamma) / cell.density
+}
+
+// sqrt(gamma p / rho), NaN where the internal energy has gone negative
+fn sound_speed(cell: &Cell, gamma: Real) -> Real {
+    (gamma * calculate_pressure(cell, gamma) / cell.density).sqrt()
+}
+
+fn velocity(cell: &Cell) -> (Real, Real) {
+    (
+        cell.momentum_x / cell.density,
+        cell.momentum_y / cell.density,
+    )
+}
+
+// A cell's state with its momentum moved to the center, averaging the faces on either side.
+// Along a closed edge the missing outer face is taken to be the inner one.
+fn centered(grid: &[Vec<Cell>], x: usize, y: usize, periodic: bool) -> Cell {
+    let (width, height) = (grid[0].len(), grid.len());
+    let left = if x > 0 || periodic {
+        (x + width - 1) % width
+    } else {
+        x
+    };
+    let up = if y > 0 || periodic {
+        (y + height - 1) % height
+    } else {
+        y
+    };
+    Cell {
+        momentum_x: (grid[y][left].momentum_x + grid[y][x].momentum_x) / 2.0,
+        momentum_y: (grid[up][x].momentum_y + grid[y][x].momentum_y) / 2.0,
+        ..grid[y][x]
+    }
+}
+
+// du/dx + dv/dy. Negative values mark compression (shocks), positive ones expansion.
+// Collocated velocities take central differences, face velocities the difference across
+// the cell. On a torus the neighbors wrap around like the flux loop's; otherwise edge
+// cells are left at zero. Solid cells are too.
+fn compute_divergence(
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    layout: Layout,
+    periodic: bool,
+) -> Vec<Vec<Real>> {
+    let mut divergence = vec![vec![0.0; grid[0].len()]; grid.len()];
+    // Velocity on the face between cells a and b, zero against a solid
+    let face = |momentum: Real, a: (usize, usize), b: (usize, usize)| {
+        if solid[a.1][a.0] || solid[b.1][b.0] {
+            0.0
+        } else {
+            2.0 * momentum / (grid[a.1][a.0].density + grid[b.1][b.0].density)
+        }
+    };
+
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            let Some(Stencil {
+                left,
+                right,
+                up,
+                down,
+            }) = grid.stencil(x, y, periodic)
+            else {
+                continue;
+            };
+            if solid[y][x] {
+                continue;
+            }
+            divergence[y][x] = match layout {
+                Layout::Collocated => {
+                    let (u_right, _) = velocity(&grid[y][right]);
+                    let (u_left, _) = velocity(&grid[y][left]);
+                    let (_, v_down) = velocity(&grid[down][x]);
+                    let (_, v_up) = velocity(&grid[up][x]);
+                    (u_right - u_left) / 2.0 + (v_down - v_up) / 2.0
+                }
+                Layout::Staggered => {
+                    let u_right = face(grid[y][x].momentum_x, (x, y), (right, y));
+                    let u_left = face(grid[y][left].momentum_x, (left, y), (x, y));
+                    let v_down = face(grid[y][x].momentum_y, (x, y), (x, down));
+                    let v_up = face(grid[up][x].momentum_y, (x, up), (x, y));
+                    (u_right - u_left) + (v_down - v_up)
+                }
+            };
+        }
+    }
+
+    divergence
+}
+
+// dv/dx - du/dy from central differences of the cell-centered velocity, obstacles counting
+// as fluid at rest. With y growing downward, positive values turn clockwise on screen. On a
+// torus the neighbors wrap around like compute_divergence's; otherwise edge cells are left
+// at zero. Solid cells are too.
+fn compute_vorticity(
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    layout: Layout,
+    periodic: bool,
+) -> Vec<Vec<Real>> {
+    let mut vorticity = vec![vec![0.0; grid[0].len()]; grid.len()];
+    let velocity_at = |x: usize, y: usize| {
+        if solid[y][x] {
+            return (0.0, 0.0);
+        }
+        match layout {
+            Layout::Collocated => velocity(&grid[y][x]),
+            Layout::Staggered => velocity(&centered(grid, x, y, periodic)),
+        }
+    };
+
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            let Some(Stencil {
+                left,
+                right,
+                up,
+                down,
+            }) = grid.stencil(x, y, periodic)
+            else {
+                continue;
+            };
+            if solid[y][x] {
+                continue;
+            }
+            let (_, v_right) = velocity_at(right, y);
+            let (_, v_left) = velocity_at(left, y);
+            let (u_down, _) = velocity_at(x, down);
+            let (u_up, _) = velocity_at(x, up);
+            vorticity[y][x] = (v_right - v_left) / 2.0 - (u_down - u_up) / 2.0;
+        }
+    }
+
+    vorticity
+}
+
+// |grad rho| from central differences, what a schlieren photograph shows and where a shock
+// is sharpest. A solid neighbor counts as more of the cell itself, so walls don't show up.
+// Edge and solid cells are left at zero.
+fn compute_density_gradient(grid: &[Vec<Cell>], solid: &[Vec<bool>]) -> Vec<Vec<Real>> {
+    let mut gradient = vec![vec![0.0; grid[0].len()]; grid.len()];
+    for y in 1..grid.len() - 1 {
+        for x in 1..grid[0].len() - 1 {
+            if solid[y][x] {
+                continue;
+            }
+            let density = |nx: usize, ny: usize| {
+                if solid[ny][nx] {
+                    grid[y][x].density
+                } else {
+                    grid[ny][nx].density
+                }
+            };
+            let dx = (density(x + 1, y) - density(x - 1, y)) / 2.0;
+            let dy = (density(x, y + 1) - density(x, y - 1)) / 2.0;
+            gradient[y][x] = (dx * dx + dy * dy).sqrt();
+        }
+    }
+    gradient
+}
+
+// Solver switches that stay fixed for a run
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    gamma: Real,
+    periodic: bool, // Wrap all four edges (obstacles included) instead of the boundary pass
+    source: Option<Source>, // Jet injected on the left edge every step
+    conduction: Real, // Heat conduction rate into and within obstacles, 0 disables it
+    // Heat a solid cell takes per degree, in the units where a cell of gas takes
+    // density / (gamma - 1). With it the heat going into the obstacle comes out of the gas
+    // next to it, and a larger capacity warms up slower. Without it the obstacle just
+    // follows the gas temperature at the conduction rate and the gas never notices.
+    solid_heat_capacity: Option<Real>,
+    wall: WallKind,
+    outlet: BoundaryKind, // The right edge
+    friction: Real,       // How fast a no-slip wall stops the flow along it, per unit time
+    viscosity: Real,      // Artificial viscosity coefficient, 0 disables it
+    density_flux: bool,   // Keep the density-gradient kick in the momentum update
+    units: Units,
+    layout: Layout,
+    scheme: Scheme,
+    gravity: Real, // Downward acceleration, cells per time squared
+    // The bottom edge is a mirror plane: only the top half of a domain that is symmetric
+    // about its centerline gets simulated
+    symmetry: bool,
+    // The top and bottom let the gas out like the left edge instead of being walls. Only
+    // the finite volume and acoustic schemes, which handle the edges themselves.
+    open_edges: bool,
+    // Every cell advances by its own CFL step instead of the global dt. Only the steady
+    // state this converges to means anything, the transient on the way there isn't
+    // physical and sim_time doesn't measure it.
+    local_dt: bool,
+    // Under-relaxation: each update only goes this fraction of the way, in (0, 1], to damp
+    // the oscillations a steady state run can settle into. Only with local_dt, in a time
+    // accurate run it would just be a smaller dt.
+    relaxation: Real,
+    // Debugging aid: the flux loop wraps around the edges like on a torus, but the boundary
+    // pass still runs afterwards. Only ever set from the window.
+    wrap_edges: bool,
+}
+
+impl Settings {
+    fn ambient(&self) -> Cell {
+        self.units.ambient(self.gamma)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            gamma: GAMMA_AIR,
+            periodic: false,
+            source: Some(Source::default()),
+            conduction: 0.0,
+            solid_heat_capacity: None,
+            wall: WallKind::Slip,
+            outlet: BoundaryKind::Outflow,
+            friction: 1.0,
+            viscosity: 0.0,
+            density_flux: true,
+            units: Units::Legacy,
+            layout: Layout::Collocated,
+            scheme: Scheme::Legacy,
+            gravity: 0.0,
+            symmetry: false,
+            open_edges: false,
+            local_dt: false,
+            relaxation: 1.0,
+            wrap_edges: false,
+        }
+    }
+}
+
+// What the gas past the right edge is, when it isn't a torus
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BoundaryKind {
+    // More of the edge cells, so whatever reaches the edge leaves
+    #[default]
+    Outflow,
+    // Subsonic outflow into a fixed back pressure: the edge cell's density and velocity
+    // with `pressure`, setting the pressure ratio across the domain. Gas leaving faster
+    // than sound can't hear it, so there it's plain outflow.
+    PressureOutlet {
+        pressure: Real,
+    },
+}
+
+impl BoundaryKind {
+    // The gas just outside the edge next to `cell`
+    fn outside(self, cell: &Cell, gamma: Real) -> Cell {
+        match self {
+            BoundaryKind::Outflow => *cell,
+            BoundaryKind::PressureOutlet { pressure } => {
+                let (vx, vy) = velocity(cell);
+                if vx >= sound_speed(cell, gamma) {
+                    return *cell;
+                }
+                Cell::from_primitives(cell.density, vx, vy, pressure, gamma)
+            }
+        }
+    }
+}
+
+// How obstacle surfaces treat the flow running along them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WallKind {
+    Slip,   // Fluid slides freely along the surface
+    NoSlip, // Tangential velocity next to the surface is damped toward zero
+}
+
+impl WallKind {
+    pub fn parse(name: &str) -> Option<WallKind> {
+        match name {
+            "slip" => Some(WallKind::Slip),
+            "no-slip" => Some(WallKind::NoSlip),
+            _ => None,
+        }
+    }
+}
+
+// Where the momentum components live
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    Collocated, // Everything at the cell center
+    // MAC grid: a cell's momentum_x sits on its right face and its momentum_y on its bottom
+    // face. The pressure difference driving a face only involves the two cells it
+    // separates, so odd and even cells can't decouple. The solver averages the faces back
+    // to the center wherever it needs a whole cell; rendering, CSV dumps and temperatures
+    // read the stored values as they are, half a cell off.
+    Staggered,
+}
+
+// How a step moves the gas. Legacy carries mass, momentum and energy across the faces but
+// keeps the original pressure-gradient forces, vertical scaling and density kick and all.
+// FiniteVolume is an actual discretization of the Euler equations, fluxes of mass,
+// momentum and energy across every face with the pressure in them, taking its dt from the
+// CFL limit; it only works on a collocated layout. Acoustic is the same thing linearized
+// about the still ambient gas, waves without shocks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheme {
+    Legacy,
+    FiniteVolume,
+    Acoustic,
+}
+
+// Vertical forces only get this share, the scheme has always done it
+const VERTICAL_FLUX_SCALE: Real = 0.1;
+
+// Courant number of every cell's own step with local timestepping
+const LOCAL_CFL: Real = 0.5;
+
+// Writes the updated grid into `new_grid`, which must have the same size. Cells the loop
+// skips (solids, and the edges unless periodic) are copied over unchanged. Returns how
+// often the density kick had to be held to the pressure push.
+fn calculate_fluxes(
+    grid: &[Vec<Cell>],
+    new_grid: &mut [Vec<Cell>],
+    solid: &[Vec<bool>],
+    solid_fraction: &[Vec<Real>],
+    settings: &Settings,
+    dt: Real,
+) -> usize {
+    let gamma = settings.gamma;
+    let periodic = settings.periodic || settings.wrap_edges;
+    let bottom = grid.height() - 1;
+    // On a torus every cell has all four neighbors, wrapping around; otherwise the edge
+    // cells are missing one and are left to apply_boundary_conditions. The stencil's
+    // indices are in bounds, so the lookups below can index directly. Every cell only
+    // reads `grid` and writes its own spot in `new_grid`, so the rows run in parallel
+    // and come out the same as one after another.
+    new_grid
+        .par_iter_mut()
+        .enumerate()
+        .map(|(y, new_row)| {
+            new_row.copy_from_slice(&grid[y]);
+            let mut clamps = 0;
+            for x in 0..grid.width() {
+                // On a symmetry plane the bottom row's missing neighbor is its own mirror image.
+                // Only momentum_y changes sign in the mirror, and nothing below reads the
+                // neighbors' momentum except through the pressure, which doesn't see the sign.
+                let stencil = match grid.stencil(x, y, periodic) {
+                    None if settings.symmetry && y == bottom && x > 0 && x < grid.width() - 1 => {
+                        Some(Stencil {
+                            left: x - 1,
+                            right: x + 1,
+                            up: y - 1,
+                            down: y,
+                        })
+                    }
+                    stencil => stencil,
+                };
+                let Some(Stencil {
+                    left,
+                    right,
+                    up,
+                    down,
+                }) = stencil
+                else {
+                    continue;
+                };
+                if solid[y][x] {
+                    continue;
+                }
+
+                // A solid neighbor still holds the gas it had when it was painted, or the air it
+                // started with, and never changes after. Read as it is, it would be a fixed
+                // state the cell keeps being pushed by. A wall shows the cell its own gas
+                // instead, like the finite volume scheme's mirror image: no gradient across it.
+                // The mirror's reversed momentum only matters to the face fluxes further down.
+                let center = grid[y][x];
+                let across = |nx: usize, ny: usize| {
+                    if solid[ny][nx] {
+                        center
+                    } else {
+                        grid[ny][nx]
+                    }
+                };
+                let (cell_left, cell_right) = (across(left, y), across(right, y));
+                let (cell_up, cell_down) = (across(x, up), across(x, down));
+
+                // Calculate pressure at current cell and its neighbors
+                let own_pressure = |x: usize, y: usize| match settings.layout {
+                    Layout::Collocated => calculate_pressure(&grid[y][x], gamma),
+                    Layout::Staggered => calculate_pressure(&centered(grid, x, y, periodic), gamma),
+                };
+                let pressure = own_pressure(x, y);
+                let pressure_at = |nx: usize, ny: usize| {
+                    if solid[ny][nx] {
+                        pressure
+                    } else {
+                        own_pressure(nx, ny)
+                    }
+                };
+                // With local timestepping every cell takes the largest step its own signal
+                // speed allows, one cell in 1 / LOCAL_CFL steps, whatever the others do
+                let dt = if settings.local_dt {
+                    let cell = match settings.layout {
+                        Layout::Collocated => center,
+                        Layout::Staggered => centered(grid, x, y, periodic),
+                    };
+                    let (vx, vy) = velocity(&cell);
+                    let signal = vx.abs() + vy.abs() + sound_speed(&cell, gamma);
+                    let local = LOCAL_CFL / signal;
+                    if local.is_finite() {
+                        local
+                    } else {
+                        dt
+                    }
+                } else {
+                    dt
+                };
+                let pressure_left = pressure_at(left, y);
+                let pressure_right = pressure_at(right, y);
+                let pressure_up = pressure_at(x, up);
+                let pressure_down = pressure_at(x, down);
+
+                // Calculate pressure gradients
+                let pressure_flux_x = (pressure_right - pressure_left) / 2.0;
+                let pressure_flux_y = VERTICAL_FLUX_SCALE * (pressure_down - pressure_up) / 2.0; // Reduce vertical flux
+
+                // Calculate density flux for stability. This isn't part of the Euler equations:
+                // p * grad(rho) / 2 pushes fluid down density gradients, loosely standing in for
+                // the mass flux the scheme didn't use to carry. Turn it off to see what the
+                // pressure gradient alone does to momentum.
+                let (density_flux_x, density_flux_y) = if settings.density_flux {
+                    (
+                        (cell_right.density - cell_left.density) / 2.0,
+                        (cell_down.density - cell_up.density) / 2.0,
+                    )
+                } else {
+                    (0.0, 0.0)
+                };
+
+                // Apply pressure flux to momentum to maintain movement, the density kick kept
+                // apart so it can be held to the push below
+                let (push, kick) = match settings.layout {
+                    Layout::Collocated => (
+                        Cell {
+                            momentum_x: -pressure_flux_x,
+                            momentum_y: -pressure_flux_y,
+                            ..Cell::zero()
+                        },
+                        Cell {
+                            momentum_x: -density_flux_x * pressure / 2.0,
+                            momentum_y: -density_flux_y * pressure / 2.0,
+                            ..Cell::zero()
+                        },
+                    ),
+                    // The same two terms across the right and bottom faces, from just the two
+                    // cells on either side and the pressure halfway between them
+                    Layout::Staggered => {
+                        let kick = |neighbor: &Cell, neighbor_pressure: Real| {
+                            if settings.density_flux {
+                                -(neighbor.density - center.density)
+                                    * (pressure + neighbor_pressure)
+                                    / 4.0
+                            } else {
+                                0.0
+                            }
+                        };
+                        (
+                            Cell {
+                                momentum_x: -(pressure_right - pressure),
+                                momentum_y: -VERTICAL_FLUX_SCALE * (pressure_down - pressure),
+                                ..Cell::zero()
+                            },
+                            Cell {
+                                momentum_x: kick(&cell_right, pressure_right),
+                                momentum_y: kick(&cell_down, pressure_down),
+                                ..Cell::zero()
+                            },
+                        )
+                    }
+                };
+
+                // The energy fluxes below do the work of the whole pressure gradient, so the
+                // energy gives back what the vertical scaling leaves undone. The kick does no
+                // work of its own, the kinetic energy it adds comes out of the internal energy,
+                // and it never pushes harder than the pressure gradient in the same direction:
+                // left alone, it drains the jet's edges until their pressure goes negative.
+                let force = {
+                    let vy = match settings.layout {
+                        Layout::Collocated => velocity(&center).1,
+                        Layout::Staggered => velocity(&centered(grid, x, y, periodic)).1,
+                    };
+                    let extra_y = (1.0 - VERTICAL_FLUX_SCALE) * (pressure_down - pressure_up) / 2.0;
+                    let mut limit = |kick: Real, push: Real| {
+                        if kick.abs() > push.abs() {
+                            clamps += 1;
+                            kick.signum() * push.abs()
+                        } else {
+                            kick
+                        }
+                    };
+                    let kick = Cell {
+                        momentum_x: limit(kick.momentum_x, push.momentum_x),
+                        momentum_y: limit(kick.momentum_y, push.momentum_y),
+                        ..kick
+                    };
+                    Cell {
+                        energy: extra_y * vy,
+                        ..push + kick
+                    }
+                };
+
+                // Gravity pulls on the mass, and the energy takes the work it does on the flow.
+                // A bottom face carries the mass on both of its sides. It's scaled down like the
+                // vertical pressure gradient, which is what holds a stratified column up.
+                let force = if settings.gravity != 0.0 {
+                    let (mass, momentum_y) = match settings.layout {
+                        Layout::Collocated => (center.density, center.momentum_y),
+                        Layout::Staggered => (
+                            (center.density + cell_down.density) / 2.0,
+                            centered(grid, x, y, periodic).momentum_y,
+                        ),
+                    };
+                    let gravity = VERTICAL_FLUX_SCALE * settings.gravity;
+                    Cell {
+                        momentum_y: force.momentum_y + mass * gravity,
+                        energy: force.energy + momentum_y * gravity,
+                        ..force
+                    }
+                } else {
+                    force
+                };
+
+                // Mass, momentum and energy change by what crosses the four faces, so whatever
+                // leaves one cell is what the next one gets. Obstacles and the top and bottom
+                // walls show the cell its mirror image, as does a symmetry plane (the bottom
+                // row's own), and nothing goes through them. The gas comes in from the jet on
+                // the left edge and leaves through the right one.
+                let wall_row =
+                    |ny: usize| !periodic && (ny == 0 || (ny == bottom && !settings.symmetry));
+                let cell_at = |nx: usize, ny: usize| match settings.layout {
+                    Layout::Collocated => grid[ny][nx],
+                    Layout::Staggered => centered(grid, nx, ny, periodic),
+                };
+                let here = cell_at(x, y);
+                let mut exchange = Cell::zero();
+                for (nx, ny, vertical, outward) in [
+                    (left, y, false, -1.0),
+                    (right, y, false, 1.0),
+                    (x, up, true, -1.0),
+                    (x, down, true, 1.0),
+                ] {
+                    let across =
+                        if solid[ny][nx] || (nx, ny) == (x, y) || (vertical && wall_row(ny)) {
+                            mirrored(&here, vertical)
+                        } else {
+                            cell_at(nx, ny)
+                        };
+                    exchange = exchange - face_flux(&here, &across, vertical, outward, gamma);
+                }
+
+                // A cell partly covered by an obstacle only has its open part to exchange with,
+                // so it changes that much slower
+                let open = 1.0 - solid_fraction[y][x];
+                new_row[x] = center + (exchange + force) * (dt * open);
+
+                // A face is as open as the more covered cell next to it, and one against a
+                // solid cell is a wall
+                if settings.layout == Layout::Staggered {
+                    let face = |momentum: Real, force: Real, nx: usize, ny: usize| {
+                        if solid[ny][nx] {
+                            0.0
+                        } else {
+                            let open = 1.0 - solid_fraction[y][x].max(solid_fraction[ny][nx]);
+                            momentum + force * dt * open
+                        }
+                    };
+                    new_row[x].momentum_x = face(center.momentum_x, force.momentum_x, right, y);
+                    new_row[x].momentum_y = face(center.momentum_y, force.momentum_y, x, down);
+                    // The bottom face is the symmetry plane, nothing crosses it
+                    if down == y {
+                        new_row[x].momentum_y = 0.0;
+                    }
+                }
+            }
+            clamps
+        })
+        .sum()
+}
+
+// What leaves `inside` across the face toward `outside` per unit time, `outward` being +1
+// when the far side is to the right or below and -1 when it's to the left or above: the
+// mass rho u, the momentum riding on it and the energy with its pressure work (E + p) u,
+// averaged over the two sides, plus dissipation scaled by the fastest wave either side
+// could send through, Rusanov's. The far side works out the same flux the other way
+// round. The pressure's push on the momentum stays with the legacy force terms, so it
+// keeps their vertical scaling and density kick.
+fn face_flux(inside: &Cell, outside: &Cell, vertical: bool, outward: Real, gamma: Real) -> Cell {
+    let carried = |cell: &Cell| {
+        let (vx, vy) = velocity(cell);
+        let normal = if vertical { vy } else { vx };
+        let flux = Cell {
+            density: cell.density * normal,
+            momentum_x: cell.momentum_x * normal,
+            momentum_y: cell.momentum_y * normal,
+            energy: (cell.energy + calculate_pressure(cell, gamma)) * normal,
+        };
+        (flux, normal.abs() + sound_speed(cell, gamma))
+    };
+    let (inside_flux, inside_speed) = carried(inside);
+    let (outside_flux, outside_speed) = carried(outside);
+    let speed = inside_speed.max(outside_speed);
+    (inside_flux + outside_flux) * (outward / 2.0) - (*outside - *inside) * (speed / 2.0)
+}
+
+// What a wall shows the cell next to it: the same gas with the velocity through the wall
+// reversed, so no mass or energy gets across
+fn mirrored(cell: &Cell, vertical: bool) -> Cell {
+    if vertical {
+        Cell {
+            momentum_y: -cell.momentum_y,
+            ..*cell
+        }
+    } else {
+        Cell {
+            momentum_x: -cell.momentum_x,
+            ..*cell
+        }
+    }
+}
+
+// How long the fastest signal takes across a cell one unit wide, 1 / (|vx| + |vy| + c)
+// with c = sqrt(gamma p / rho). None for a cell that has lost its density or its pressure
+// and so has no sound speed to go by.
+fn crossing_time(cell: &Cell, gamma: Real) -> Option<Real> {
+    let (vx, vy) = velocity(cell);
+    let dt = 1.0 / (vx.abs() + vy.abs() + sound_speed(cell, gamma));
+    (cell.density > 0.0 && dt.is_finite() && dt > 0.0).then_some(dt)
+}
+
+// The unsplit update's share of a CFL limit. The step never goes past DEFAULT_DT, so an
+// empty or still field, with no limit at all, doesn't take an endless one.
+fn cfl_step(limit: Option<Real>) -> Real {
+    limit.map_or(DEFAULT_DT, |limit| {
+        (finite_volume::FV_CFL * limit).min(DEFAULT_DT)
+    })
+}
+
+// The largest stable dt for a bare grid of one gas with cells `dx` across, going by its
+// fastest cell. A simulation has Simulation::cfl_dt, which knows its solids and gases.
+fn compute_max_dt(grid: &[Vec<Cell>], gamma: Real, dx: Real) -> Real {
+    let limit = grid
+        .iter()
+        .flatten()
+        .filter_map(|cell| crossing_time(cell, gamma))
+        .reduce(Real::min);
+    cfl_step(limit.map(|limit| limit * dx))
+}
+
+// The freshly computed buffer becomes the grid, the old grid becomes next step's scratch
+fn update_grid(grid: &mut Vec<Vec<Cell>>, new_grid: &mut Vec<Vec<Cell>>) {
+    std::mem::swap(grid, new_grid);
+}
+
+// A jet on the left edge, centered vertically
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub width: usize, // In cells
+    pub emission_rate: Real,
+    pub velocity: Real,
+    // A sinusoidal cross-flow on the jet to break the symmetry of a steady inflow,
+    // zero amplitude leaves it off
+    #[serde(default)]
+    pub perturbation_amplitude: Real,
+    #[serde(default)]
+    pub perturbation_frequency: Real, // Cycles per unit of sim time
+    // Add the mass with the momentum and energy it carries, `velocity` then being the
+    // speed of the incoming gas, instead of overwriting the momentum
+    #[serde(default)]
+    pub conservative: bool,
+    // Index into the scenario's tracers of the dye the jet carries in
+    #[serde(default)]
+    pub tracer: Option<usize>,
+    // Factors on `velocity` and on the mass coming in (the emission rate, or the inflow
+    // density in physical units) that change over the run, for gusts
+    #[serde(default)]
+    pub velocity_schedule: Option<InflowSchedule>,
+    #[serde(default)]
+    pub density_schedule: Option<InflowSchedule>,
+    // Backs the jet off where the inlet gas already moves or pushes too hard, see InflowLimit
+    #[serde(default)]
+    pub limit: Option<InflowLimit>,
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source {
+            width: 20,
+            emission_rate: 20000.0,
+            velocity: 65000.0,
+            perturbation_amplitude: 0.0,
+            perturbation_frequency: 0.0,
+            conservative: false,
+            tracer: None,
+            velocity_schedule: None,
+            density_schedule: None,
+            limit: None,
+        }
+    }
+}
+
+impl Source {
+    // The y-velocity of the jet at time t. Only a function of t so runs are reproducible.
+    fn cross_flow(&self, t: Real) -> Option<Real> {
+        (self.perturbation_amplitude > 0.0).then(|| {
+            self.perturbation_amplitude * (2.0 * consts::PI * self.perturbation_frequency * t).sin()
+        })
+    }
+
+    // The x-velocity of the jet at time t
+    fn velocity_at(&self, t: Real) -> Real {
+        self.velocity * self.velocity_schedule.map_or(1.0, |s| s.factor(t))
+    }
+
+    // What the density schedule scales the incoming mass by at time t
+    fn density_factor(&self, t: Real) -> Real {
+        self.density_schedule.map_or(1.0, |s| s.factor(t))
+    }
+}
+
+// Part of the way from `current` to `target`, all of it at a share of 1
+fn blend<T>(current: T, target: T, share: Real) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Real, Output = T>,
+{
+    if share >= 1.0 {
+        target
+    } else {
+        current + (target - current) * share
+    }
+}
+
+// `cells` are the jet rows to feed, each with the share of the inflow it gets. Rows where an
+// obstacle sits on the inflow edge are left out, so no fluid is pumped into it.
+fn add_fluid_source(
+    grid: &mut [Vec<Cell>],
+    cells: &[(usize, Real)],
+    emission_rate: Real,
+    fluid_velocity: Real,
+    cross_flow: Option<Real>,
+    dt: Real,
+) {
+    for &(y, share) in cells {
+        let cell = &mut grid[y][0];
+        // The momentum is set rather than brought in, so the kinetic energy that comes
+        // with it has to be added too, or it eats the internal energy and the pressure
+        // goes negative under a fast jet
+        let internal = cell.energy - kinetic_energy(cell);
+        cell.density += emission_rate * share * dt;
+        cell.momentum_x = blend(cell.momentum_x, fluid_velocity, share);
+        if let Some(cross_flow) = cross_flow {
+            cell.momentum_y = blend(cell.momentum_y, cross_flow, share);
+        }
+        cell.energy = internal + (AIR_ENERGY + 100.0) * share * dt + kinetic_energy(cell);
+    }
+}
+
+// Mass `added` per unit time arrives moving at `velocity` with `specific_energy` of heat per
+// unit mass, so the momentum and energy go up by exactly what that mass brings along
+fn inject_fluid(
+    grid: &mut [Vec<Cell>],
+    cells: &[(usize, Real)],
+    added: Real,
+    velocity: (Real, Real),
+    specific_energy: Real,
+    dt: Real,
+) {
+    let (vx, vy) = velocity;
+    for &(y, share) in cells {
+        let mass = added * share * dt;
+        let cell = &mut grid[y][0];
+        cell.density += mass;
+        cell.momentum_x += mass * vx;
+        cell.momentum_y += mass * vy;
+        cell.energy += mass * (specific_energy + (vx * vx + vy * vy) / 2.0);
+    }
+}
+
+// The physical-units source: the jet cells are simply held at the inflow state, or pulled
+// only part of the way there when throttled
+fn set_inflow(grid: &mut [Vec<Cell>], cells: &[(usize, Real)], inflow: Cell) {
+    for &(y, share) in cells {
+        grid[y][0] = blend(grid[y][0], inflow, share);
+    }
+}
+
+// `width` rows centered on the left edge, cut off at the grid. On a symmetry plane the
+// center is the bottom edge, so only the upper half of the jet is left.
+fn jet_rows(height: usize, width: usize, symmetry: bool) -> Range<usize> {
+    let center_y = if symmetry { height } else { height / 2 };
+    center_y.saturating_sub(width / 2)..(center_y + width / 2).min(height)
+}
+
+// A square a third of the height across in the middle of the grid, cells 30..50 by 20..40
+// on the default 80x60 one
+fn create_solid_object(width: usize, height: usize) -> Vec<Vec<bool>> {
+    let mut solid_object = vec![vec![false; width]; height];
+    let (center_x, center_y) = norm_to_cell(0.5, 0.5, width, height);
+    let half = BLOCK_SIZE * height as Real / 2.0;
+    let span = |center: Real, cells: usize| {
+        ((center - half).round().max(0.0) as usize)..((center + half).round() as usize).min(cells)
+    };
+
+    for row in &mut solid_object[span(center_y, height)] {
+        for cell in &mut row[span(center_x, width)] {
+            *cell = true;
+        }
+    }
+
+    solid_object
+}
+
+// The create_solid_object block as a shape, turned by `angle` degrees about its center
+fn create_rotated_block(angle: Real, width: usize, height: usize) -> Shape {
+    Shape::RotatedRectangle {
+        center_x: 0.5,
+        center_y: 0.5,
+        width: BLOCK_SIZE,
+        height: BLOCK_SIZE,
+        angle,
+    }
+    .scaled(width, height)
+}
+
+// Obstacles that reach the first interior row/column are extended onto the edge cells,
+// so the wall continues into the exterior instead of leaving a one-cell fluid gap that the
+// boundary pass would fill by copying from inside the solid.
+#[allow(clippy::needless_range_loop)] // x indexes several rows at once
+fn close_boundary_obstacles(solid_fraction: &mut [Vec<Real>], symmetry: bool) {
+    let width = solid_fraction[0].len();
+    let height = solid_fraction.len();
+
+    for row in solid_fraction.iter_mut() {
+        row[0] = row[0].max(row[1]);
+        row[width - 1] = row[width - 1].max(row[width - 2]);
+    }
+
+    for x in 0..width {
+        solid_fraction[0][x] = solid_fraction[0][x].max(solid_fraction[1][x]);
+        // A symmetry plane isn't a boundary pass edge, its row is simulated as it is
+        if !symmetry {
+            solid_fraction[height - 1][x] =
+                solid_fraction[height - 1][x].max(solid_fraction[height - 2][x]);
+        }
+    }
+}
+
+// Edge cells covered by an obstacle are left alone; they behave like any other solid cell
+#[allow(clippy::needless_range_loop)] // x indexes several rows at once
+fn apply_boundary_conditions(grid: &mut [Vec<Cell>], solid: &[Vec<bool>], settings: &Settings) {
+    let width = grid[0].len();
+    let height = grid.len();
+    let symmetry = settings.symmetry;
+
+    let jet = settings
+        .source
+        .map_or(0..0, |source| jet_rows(height, source.width, symmetry));
+
+    // Zero out vertical momentum at left and right boundaries
+    for (y, (row, solid_row)) in grid.iter_mut().zip(solid).enumerate() {
+        // Left boundary: more of the gas inside, horizontal velocity and all. The jet's
+        // cells keep the gas it puts in them.
+        if !solid_row[0] && !jet.contains(&y) {
+            row[0] = Cell {
+                momentum_y: 0.0,
+                ..row[1]
+            };
+        }
+        if !solid_row[width - 1] {
+            // Right boundary: the gas inside going on out, or into the back pressure, which
+            // sets the energy to match
+            let inside = Cell {
+                momentum_y: 0.0,
+                ..row[width - 2]
+            };
+            row[width - 1] = settings.outlet.outside(&inside, settings.gamma);
+        }
+    }
+
+    // Apply conditions at the top and bottom boundaries
+    for x in 0..width {
+        // Optional: Match density and energy at the boundaries to prevent pressure imbalances
+        if !solid[0][x] {
+            grid[0][x].momentum_y = 0.0; // Top boundary
+            grid[0][x].density = grid[1][x].density; // Match density at top
+            grid[0][x].energy = grid[1][x].energy; // Match energy at top
+        }
+        // A symmetry plane's row is updated by the flux loop like the interior
+        if !solid[height - 1][x] && !symmetry {
+            grid[height - 1][x].momentum_y = 0.0; // Bottom boundary
+            grid[height - 1][x].density = grid[height - 2][x].density; // Match density at bottom
+            grid[height - 1][x].energy = grid[height - 2][x].energy; // Match energy at bottom
+        }
+    }
+}
+
+// A NACA 4-digit airfoil across the middle of the grid, in grid fractions: the chord
+// spans half the width on the default grid. The camber, its position and the thickness
+// are fractions of the chord, the angle of attack in degrees nose up.
+fn create_naca_airfoil(
+    camber: Real,
+    camber_position: Real,
+    thickness: Real,
+    angle_of_attack: Real,
+) -> Shape {
+    Shape::Airfoil {
+        center_x: 0.5,
+        center_y: 0.5,
+        chord: 2.0 / 3.0,
+        camber,
+        camber_position,
+        thickness,
+        angle: angle_of_attack,
+    }
+}
+
+// A NACA 2412 at 4 degrees, the built-in airfoil Tab cycles to
+fn create_airfoil() -> Shape {
+    create_naca_airfoil(0.02, 0.4, 0.12, 4.0)
+}
+
+// The built-in obstacles Tab cycles through, all centered and sized relative to the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Obstacle {
+    Block,
+    Cylinder,
+    Airfoil,
+    Plate,
+    Empty,
+}
+
+impl Obstacle {
+    fn next(self) -> Obstacle {
+        match self {
+            Obstacle::Block => Obstacle::Cylinder,
+            Obstacle::Cylinder => Obstacle::Airfoil,
+            Obstacle::Airfoil => Obstacle::Plate,
+            Obstacle::Plate => Obstacle::Empty,
+            Obstacle::Empty => Obstacle::Block,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Obstacle::Block => "block",
+            Obstacle::Cylinder => "cylinder",
+            Obstacle::Airfoil => "airfoil",
+            Obstacle::Plate => "plate",
+            Obstacle::Empty => "empty",
+        }
+    }
+
+    // In grid fractions, see Shape::scaled. On the default 80x60 grid the block lands on
+    // the same cells as create_solid_object.
+    fn shapes(self) -> Vec<Shape> {
+        match self {
+            Obstacle::Block => vec![Shape::Rectangle {
+                x: 3.0 / 8.0,
+                y: 1.0 / 3.0,
+                width: BLOCK_SIZE,
+                height: BLOCK_SIZE,
+            }],
+            Obstacle::Cylinder => vec![Shape::Ellipse {
+                center_x: 0.5,
+                center_y: 0.5,
+                radius_x: 1.0 / 6.0,
+                radius_y: 1.0 / 6.0,
+            }],
+            Obstacle::Airfoil => vec![create_airfoil()],
+            Obstacle::Plate => vec![Shape::RotatedRectangle {
+                center_x: 0.5,
+                center_y: 0.5,
+                width: 1.0 / 3.0,
+                height: 1.0 / 30.0,
+                angle: 20.0,
+            }],
+            Obstacle::Empty => Vec::new(),
+        }
+    }
+}
+
+// What part a cell plays in the setup, for the regions view
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    Solid,
+    Inflow,   // A jet cell
+    Outflow,  // On a left or right edge the gas can leave through
+    Wall,     // Next to a slip wall, an obstacle or the top or bottom edge
+    NoSlip,   // The same with no-slip walls
+    Sponge,   // Inside the sponge layer
+    Interior, // None of the above
+}
+
+// What a step did to the field, for callers that want to react to it
+#[derive(Debug, Clone, Copy, Serialize)]
+struct StepReport {
+    min_density: Real, // Over fluid cells; NaN as soon as any cell is NaN
+    max_speed: Real,
+    max_mach: Real, // Over fluid cells with a sound speed, so no negative pressures
+    clamps_applied: usize, // Updates that had to be limited to keep a value in range
+    inflow_mach: Real, // Fastest jet cell, inflow speed over sound speed; 0 without a jet
+    dt: Real,
+}
+
+impl StepReport {
+    fn measure(
+        grid: &[Vec<Cell>],
+        solid: &[Vec<bool>],
+        gamma: Real,
+        clamps_applied: usize,
+        dt: Real,
+    ) -> Self {
+        let mut report = StepReport {
+            min_density: Real::INFINITY,
+            max_speed: 0.0,
+            max_mach: 0.0,
+            clamps_applied,
+            inflow_mach: 0.0,
+            dt,
+        };
+        for (cell, _) in grid
+            .iter()
+            .flatten()
+            .zip(solid.iter().flatten())
+            .filter(|(_, &solid)| !solid)
+        {
+            let (vx, vy) = velocity(cell);
+            let speed = vx.hypot(vy);
+            // Written so a NaN sticks instead of being skipped like Real::min would.
+            // Nothing compares less than NaN, so once it's in it stays.
+            if cell.density.is_nan() || cell.density < report.min_density {
+                report.min_density = cell.density;
+            }
+            if speed.is_nan() || speed > report.max_speed {
+                report.max_speed = speed;
+            }
+            report.max_mach = report.max_mach.max(speed / sound_speed(cell, gamma));
+        }
+        report
+    }
+
+    // Positive densities and finite speeds; anything else won't recover
+    fn is_stable(&self) -> bool {
+        self.min_density > 0.0 && self.max_speed.is_finite()
+    }
+
+    fn warn_unstable(&self, step: u64) {
+        eprintln!(
+            "Warning: unstable at step {} (min density {:.3e}, max speed {:.3e}, dt {:.3e}, {} clamps)",
+            step, self.min_density, self.max_speed, self.dt, self.clamps_applied
+        );
+    }
+
+    fn is_supersonic(&self) -> bool {
+        self.inflow_mach > 1.0
+    }
+
+    fn warn_supersonic(&self, step: u64) {
+        eprintln!(
+            "Warning: supersonic inflow at step {} (Mach {:.3e}), holding the jet cells at the inflow state",
+            step, self.inflow_mach
+        );
+    }
+}
+
+struct Simulation {
+    grid: Vec<Vec<Cell>>,
+    scratch: Vec<Vec<Cell>>, // Where the next grid gets computed, swapped with `grid` each step
+    solid: Vec<Vec<bool>>,   // Fully covered cells, the solver leaves these alone
+    solid_fraction: Vec<Vec<Real>>, // How much of each cell the obstacle covers, 0 to 1
+    settings: Settings,
+    // Temperature of every cell's solid material. Only meaningful where `solid` is set,
+    // and it only changes when conduction is enabled
+    solid_temperature: Vec<Vec<Real>>,
+    step_count: u64,
+    sim_time: Real,        // Sum of every dt taken so far
+    stop_at: Option<Real>, // The step that would pass this time is shortened to land on it
+    // Scales the automatic dt, up to 1, for backing off during transients. A manual dt
+    // is taken as it is.
+    dt_safety: Real,
+    fixed_dt: Option<Real>, // Taken every step instead of the automatic dt, safety and all
+    sampling: Sampling,     // How sample_at blends the cells around a point
+    max_hold: Option<MaxHold>, // Updated after every step while it runs
+    flow_meter: Option<FlowMeter>, // Reads the fluxes at the start of every step
+    mass_balance: Option<MassBalance>, // Weighs the fluid either side of every update
+    drag: Vec<Vec<Real>>,   // Porous drag rate of every cell, per unit time
+    // Kinetic energy the artificial viscosity turned into heat in every cell during the last
+    // step, per unit time
+    dissipation: Vec<Vec<Real>>,
+    sponge: Option<Sponge>,
+    forcing: Option<Forcing>,   // Stirs the gas every step
+    tracers: Vec<TracerField>,  // Carried along after every step
+    material: Option<Material>, // Two gases, one of the tracers saying which is where
+    // Every step's solver part done again by the reference one and compared, see reference.rs
+    reference: Option<ReferenceCheck>,
+    wake: Option<WakeSignal>, // Recorded after every step, for the strouhal report
+    allocations: Option<AllocationCheck>, // What every step allocates, in counting builds
+}
+
+impl Simulation {
+    fn new(grid: Vec<Vec<Cell>>, solid_fraction: Vec<Vec<Real>>, settings: Settings) -> Simulation {
+        let solid = solid_fraction
+            .iter()
+            .map(|row| row.iter().map(|&f| f >= 1.0).collect())
+            .collect();
+        let ambient_temperature = temperature(&settings.ambient(), settings.gamma);
+        let solid_temperature = vec![vec![ambient_temperature; grid[0].len()]; grid.len()];
+        let drag = vec![vec![0.0; grid[0].len()]; grid.len()];
+        Simulation {
+            scratch: grid.clone(),
+            grid,
+            solid,
+            solid_fraction,
+            settings,
+            solid_temperature,
+            step_count: 0,
+            sim_time: 0.0,
+            stop_at: None,
+            dt_safety: 1.0,
+            fixed_dt: None,
+            sampling: Sampling::Primitive,
+            max_hold: None,
+            flow_meter: None,
+            mass_balance: None,
+            dissipation: drag.clone(),
+            drag,
+            sponge: None,
+            forcing: None,
+            tracers: Vec::new(),
+            material: None,
+            reference: None,
+            wake: None,
+            allocations: None,
+        }
+    }
+
+    // Turns the flow around: every momentum changes sign, so the gas (and any tracer riding
+    // on it) retraces its path. Not exactly, the scheme's diffusion never runs backward.
+    // Sources and gravity keep pushing the way they did.
+    fn reverse(&mut self) {
+        for cell in self.grid.iter_mut().flatten() {
+            cell.momentum_x = -cell.momentum_x;
+            cell.momentum_y = -cell.momentum_y;
+        }
+    }
+
+    fn report_tracer_departure(&self, start: &[TracerField]) {
+        for (i, departure) in tracer::departure(&self.tracers, start).iter().enumerate() {
+            println!(
+                "Tracer {}: mean difference from its start {:.4e}",
+                i, departure
+            );
+        }
+    }
+
+    // The flow velocity at the middle of cell (x, y), whatever the layout
+    fn cell_velocity(&self, x: usize, y: usize) -> (Real, Real) {
+        match self.settings.layout {
+            Layout::Collocated => velocity(&self.grid[y][x]),
+            Layout::Staggered => velocity(&centered(&self.grid, x, y, self.settings.periodic)),
+        }
+    }
+
+    // The adiabatic index of the gas in cell (x, y)
+    fn gamma_at(&self, x: usize, y: usize) -> Real {
+        match self.material {
+            Some(material) => material.gamma(self.tracers[material.tracer].concentration[y][x]),
+            None => self.settings.gamma,
+        }
+    }
+
+    // Every cell's gamma, when there's more than the one in the settings
+    fn gamma_field(&self) -> Option<Vec<Vec<Real>>> {
+        self.material?;
+        Some(
+            (0..self.grid.height())
+                .map(|y| {
+                    (0..self.grid.width())
+                        .map(|x| self.gamma_at(x, y))
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    // Advances the simulation by one step and reports on the result
+    fn step(&mut self, dt_override: Option<Real>) -> StepReport {
+        self.step_with(dt_override, |_, _| {})
+    }
+
+    // A step with `hook` run on the grid once the step's own physics is done, given the
+    // time the step ends at. For forcing, measurements or boundary tweaks of a caller's own,
+    // which the max hold, the wake probe and the report then see like everything else.
+    fn step_with(
+        &mut self,
+        dt_override: Option<Real>,
+        mut hook: impl FnMut(&mut [Vec<Cell>], Real),
+    ) -> StepReport {
+        if let Some(check) = &mut self.allocations {
+            check.start();
+        }
+        let mut dt = dt_override
+            .or(self.fixed_dt)
+            .unwrap_or_else(|| self.automatic_dt());
+        let landing = self.stop_at.filter(|&stop| self.sim_time + dt >= stop);
+        if let Some(stop) = landing {
+            dt = stop - self.sim_time;
+        }
+
+        // Add a fluid source on the left, remembering the jet cells' new state
+        let mut inflow = Vec::new();
+        if let Some(source) = self.settings.source {
+            let rows = jet_rows(self.grid.len(), source.width, self.settings.symmetry);
+            let jet_velocity = source.velocity_at(self.sim_time);
+            let density_factor = source.density_factor(self.sim_time);
+            let cells: Vec<(usize, Real)> = rows
+                .clone()
+                .filter(|&y| !self.solid[y][0])
+                .map(|y| {
+                    let share = source.limit.map_or(1.0, |limit| {
+                        let cell = &self.grid[y][0];
+                        let (vx, vy) = velocity(cell);
+                        let pressure = calculate_pressure(cell, self.gamma_at(0, y));
+                        limit.factor((vx * vx + vy * vy).sqrt(), pressure)
+                    });
+                    (y, share)
+                })
+                .collect();
+            match self.settings.units {
+                Units::Legacy if source.conservative => {
+                    // The new gas is as warm as the ambient air
+                    let ambient = self.settings.ambient();
+                    let specific_energy =
+                        (ambient.energy - kinetic_energy(&ambient)) / ambient.density;
+                    inject_fluid(
+                        &mut self.grid,
+                        &cells,
+                        source.emission_rate * density_factor,
+                        (
+                            jet_velocity,
+                            source.cross_flow(self.sim_time).unwrap_or(0.0),
+                        ),
+                        specific_energy,
+                        dt,
+                    )
+                }
+                Units::Legacy => add_fluid_source(
+                    &mut self.grid,
+                    &cells,
+                    source.emission_rate * density_factor,
+                    jet_velocity,
+                    source.cross_flow(self.sim_time),
+                    dt,
+                ),
+                Units::Physical => {
+                    let (density, _, _, pressure) =
+                        primitives_from_cell(&self.settings.ambient(), self.settings.gamma);
+                    let inflow = Cell::from_primitives(
+                        density * density_factor,
+                        jet_velocity,
+                        source.cross_flow(self.sim_time).unwrap_or(0.0),
+                        pressure,
+                        self.settings.gamma,
+                    );
+                    set_inflow(&mut self.grid, &cells, inflow);
+                }
+            }
+            inflow = rows
+                .filter(|&y| !self.solid[y][0])
+                .map(|y| (y, self.grid[y][0]))
+                .collect();
+        }
+        let gamma = self.settings.gamma;
+        let inflow_mach = inflow
+            .iter()
+            .map(|(_, cell)| velocity(cell).0 / sound_speed(cell, gamma))
+            .fold(0.0, Real::max);
+
+        let gammas = self.gamma_field();
+        if let Some(mut meter) = self.flow_meter.take() {
+            meter.record(self, gammas.as_deref(), dt);
+            self.flow_meter = Some(meter);
+        }
+        if let Some(mut balance) = self.mass_balance.take() {
+            balance.start(self, gammas.as_deref(), dt);
+            self.mass_balance = Some(balance);
+        }
+        let expected = self.reference.is_some_and(|check| check.active()).then(|| {
+            reference::advance(
+                &self.grid,
+                &self.solid,
+                &self.solid_fraction,
+                &self.settings,
+                dt,
+            )
+        });
+        let mut clamps = advance(
+            &mut self.grid,
+            &mut self.scratch,
+            &self.solid,
+            &self.solid_fraction,
+            &self.settings,
+            gammas.as_deref(),
+            dt,
+        );
+        if let Some(mut balance) = self.mass_balance.take() {
+            balance.finish(self);
+            self.mass_balance = Some(balance);
+        }
+        if let (Some(check), Some(expected)) = (&mut self.reference, expected) {
+            check.record(&expected, &self.grid, &self.solid, self.step_count + 1);
+        }
+        clamps += self.apply_drag(dt);
+        clamps += self.apply_viscosity(dt);
+        self.apply_sponge(dt);
+        self.apply_forcing(dt);
+        self.advect_tracers(dt);
+        // Faster than sound into the domain every characteristic at the inlet points inward,
+        // so nothing from inside may reach the jet cells: the boundary pass extrapolating
+        // the interior's momentum into them is undone and they keep the state just set
+        if inflow_mach > 1.0 && !self.settings.periodic {
+            for (y, cell) in inflow {
+                self.grid[y][0] = cell;
+            }
+        }
+
+        if self.settings.conduction > 0.0 {
+            self.conduct_heat(dt);
+        }
+        // Set rather than summed on the last step so it hits the target without rounding error
+        let end = landing.unwrap_or(self.sim_time + dt);
+        hook(&mut self.grid, end);
+
+        if let Some(mut hold) = self.max_hold.take() {
+            hold.update(self);
+            self.max_hold = Some(hold);
+        }
+
+        self.step_count += 1;
+        self.sim_time = end;
+        if let Some(mut wake) = self.wake.take() {
+            wake.record(self);
+            self.wake = Some(wake);
+        }
+        let report = StepReport {
+            inflow_mach,
+            ..StepReport::measure(&self.grid, &self.solid, gamma, clamps, dt)
+        };
+        if let Some(check) = &mut self.allocations {
+            check.finish(self.step_count);
+        }
+        report
+    }
+
+    // Darcy drag in the porous cells, subtracting drag * momentum * dt. The energy is left
+    // alone, so the kinetic energy taken out stays behind as heat. Clamped like the wall
+    // friction so a drag * dt over 1 stops the flow instead of reversing it; returns how
+    // often that happened.
+    fn apply_drag(&mut self, dt: Real) -> usize {
+        let mut clamps = 0;
+        for ((row, drag_row), solid_row) in self.grid.iter_mut().zip(&self.drag).zip(&self.solid) {
+            for ((cell, &drag), &solid) in row.iter_mut().zip(drag_row).zip(solid_row) {
+                if solid || drag == 0.0 {
+                    continue;
+                }
+                let factor = 1.0 - drag * dt;
+                if factor < 0.0 {
+                    clamps += 1;
+                }
+                cell.momentum_x *= factor.max(0.0);
+                cell.momentum_y *= factor.max(0.0);
+            }
+        }
+        clamps
+    }
+
+    // Artificial viscosity: neighboring fluid cells even out their velocities across each
+    // face, at `viscosity` times how fast the gas on either side is being squeezed, so it
+    // works in shocks and against obstacles and leaves smooth or expanding flow alone. The
+    // momentum one side loses the other gains, and the energy is left alone, so the kinetic
+    // energy evened out stays behind as heat; `dissipation` keeps how much of it every cell
+    // got. A face asked to do more than MAX_VISCOUS_SHARE in one step is held to it;
+    // returns how often that happened.
+    fn apply_viscosity(&mut self, dt: Real) -> usize {
+        for row in &mut self.dissipation {
+            row.fill(0.0);
+        }
+        let viscosity = self.settings.viscosity;
+        if viscosity == 0.0 {
+            return 0;
+        }
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let compression: Vec<Vec<Real>> = compute_divergence(
+            &self.grid,
+            &self.solid,
+            self.settings.layout,
+            self.settings.periodic,
+        )
+        .iter()
+        .map(|row| row.iter().map(|&d| (-d).max(0.0)).collect())
+        .collect();
+        let periodic = self.settings.periodic;
+        let mut change = vec![vec![(0.0, 0.0); width]; height];
+        let mut clamps = 0;
+        for y in 0..height {
+            for x in 0..width {
+                if self.solid[y][x] {
+                    continue;
+                }
+                // The right and bottom faces, so every face is taken once
+                for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                    if !periodic && (nx == width || ny == height) {
+                        continue;
+                    }
+                    let (nx, ny) = (nx % width, ny % height);
+                    if self.solid[ny][nx] {
+                        continue;
+                    }
+                    let rate = viscosity * compression[y][x].max(compression[ny][nx]);
+                    let mut share = rate * dt;
+                    if share > MAX_VISCOUS_SHARE {
+                        share = MAX_VISCOUS_SHARE;
+                        clamps += 1;
+                    }
+                    if share.is_nan() || share <= 0.0 {
+                        continue;
+                    }
+                    let (a, b) = (self.grid[y][x], self.grid[ny][nx]);
+                    let ((ax, ay), (bx, by)) = (velocity(&a), velocity(&b));
+                    let density = a.density.min(b.density);
+                    let (dx, dy) = (bx - ax, by - ay);
+                    let (fx, fy) = (share * density * dx, share * density * dy);
+                    change[y][x].0 += fx;
+                    change[y][x].1 += fy;
+                    change[ny][nx].0 -= fx;
+                    change[ny][nx].1 -= fy;
+                    // Split evenly between the two sides
+                    let heat = share * density * (dx * dx + dy * dy) / dt / 2.0;
+                    self.dissipation[y][x] += heat;
+                    self.dissipation[ny][nx] += heat;
+                }
+            }
+        }
+        for (row, change_row) in self.grid.iter_mut().zip(&change) {
+            for (cell, &(dx, dy)) in row.iter_mut().zip(change_row) {
+                cell.momentum_x += dx;
+                cell.momentum_y += dy;
+            }
+        }
+        clamps
+    }
+
+    // Moves the fluid in the sponge layer a fraction rate * dt (at most all the way) toward
+    // the free stream
+    fn apply_sponge(&mut self, dt: Real) {
+        let Some(sponge) = &self.sponge else {
+            return;
+        };
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let (density, _, _, pressure) =
+            primitives_from_cell(&self.settings.ambient(), self.settings.gamma);
+        let target =
+            Cell::from_primitives(density, sponge.velocity, 0.0, pressure, self.settings.gamma);
+        for (y, row) in self.grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let rate = sponge.rate(x, y, width, height);
+                if rate == 0.0 || self.solid[y][x] {
+                    continue;
+                }
+                *cell = *cell + (target - *cell) * (rate * dt).min(1.0);
+            }
+        }
+    }
+
+    // Pushes every fluid cell with the forcing's acceleration, rho a dt more momentum and the
+    // kinetic energy that brings. The energy it adds is then taken back out of the heat,
+    // spread over the cells by how much each holds, so the total stays where it was: what
+    // the stirring puts in only gets turned back into heat by the scheme's diffusion, and
+    // would otherwise pile up without end.
+    fn apply_forcing(&mut self, dt: Real) {
+        let Some(forcing) = self.forcing else {
+            return;
+        };
+        let acceleration = forcing.field(self.grid.width(), self.grid.height(), self.sim_time);
+        let mut added = 0.0;
+        for ((row, accel_row), solid_row) in
+            self.grid.iter_mut().zip(&acceleration).zip(&self.solid)
+        {
+            for ((cell, &(ax, ay)), &solid) in row.iter_mut().zip(accel_row).zip(solid_row) {
+                if solid {
+                    continue;
+                }
+                let before = kinetic_energy(cell);
+                cell.momentum_x += cell.density * ax * dt;
+                cell.momentum_y += cell.density * ay * dt;
+                let change = kinetic_energy(cell) - before;
+                cell.energy += change;
+                added += change;
+            }
+        }
+        let heat: Real = self
+            .grid
+            .iter()
+            .flatten()
+            .zip(self.solid.iter().flatten())
+            .filter(|&(_, &solid)| !solid)
+            .map(|(cell, _)| (cell.energy - kinetic_energy(cell)).max(0.0))
+            .sum();
+        if added == 0.0 || heat <= 0.0 {
+            return;
+        }
+        for (row, solid_row) in self.grid.iter_mut().zip(&self.solid) {
+            for (cell, &solid) in row.iter_mut().zip(solid_row) {
+                if !solid {
+                    cell.energy -= added * (cell.energy - kinetic_energy(cell)).max(0.0) / heat;
+                }
+            }
+        }
+    }
+
+    // Carries the tracers along with the new velocities, then tops their sources back up
+    fn advect_tracers(&mut self, dt: Real) {
+        if self.tracers.is_empty() {
+            return;
+        }
+        let velocities: Vec<Vec<(Real, Real)>> = self
+            .centered_grid()
+            .iter()
+            .map(|row| row.iter().map(velocity).collect())
+            .collect();
+        let jet = self.settings.source.and_then(|source| {
+            let rows = jet_rows(self.grid.len(), source.width, self.settings.symmetry);
+            Some((source.tracer?, rows))
+        });
+        let periodic = self.settings.periodic;
+        for (i, field) in self.tracers.iter_mut().enumerate() {
+            field.concentration =
+                tracer::advect(&field.concentration, &velocities, &self.solid, dt, periodic);
+            field.hold_sources();
+            if let Some((_, rows)) = jet.clone().filter(|&(tracer, _)| tracer == i) {
+                for y in rows.filter(|&y| !self.solid[y][0]) {
+                    field.concentration[y][0] = 1.0;
+                }
+            }
+        }
+    }
+
+    // Swaps in a new obstacle mid-run. Cells the old one covered get their fluid state back
+    // as it was when they were covered, which is close enough to ambient for a demo.
+    fn set_obstacle(&mut self, solid_fraction: Vec<Vec<Real>>) {
+        self.solid = solid_fraction
+            .iter()
+            .map(|row| row.iter().map(|&f| f >= 1.0).collect())
+            .collect();
+        self.solid_fraction = solid_fraction;
+        let ambient_temperature = temperature(&self.settings.ambient(), self.settings.gamma);
+        for row in self.solid_temperature.iter_mut() {
+            row.fill(ambient_temperature);
+        }
+    }
+
+    // Pushes the fluid around (cx, cy), in cells, radially outward. Only the momentum changes,
+    // and the energy by the kinetic energy that adds, so no mass or heat goes in and the
+    // pulse that comes out travels at the sound speed. Face momenta are pushed along the
+    // direction from the center to their face.
+    fn poke(&mut self, cx: Real, cy: Real, radius: Real) {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let periodic = self.settings.periodic;
+        let (layout, gamma) = (self.settings.layout, self.settings.gamma);
+        let kinetic = |grid: &[Vec<Cell>], x: usize, y: usize| match layout {
+            Layout::Collocated => kinetic_energy(&grid[y][x]),
+            Layout::Staggered => kinetic_energy(&centered(grid, x, y, periodic)),
+        };
+        // Cells whose kinetic energy can change, a face further than the staggered ones
+        let reach = radius.ceil() as usize + 1;
+        let xs = (cx as usize).saturating_sub(reach)..(cx as usize + reach + 1).min(width);
+        let ys = (cy as usize).saturating_sub(reach)..(cy as usize + reach + 1).min(height);
+        let mut internal = Vec::new();
+        for y in ys.clone() {
+            for x in xs.clone() {
+                internal.push((x, y, self.grid[y][x].energy - kinetic(&self.grid, x, y)));
+            }
+        }
+
+        // Velocity added at (px, py) along one axis, `along` picking x or y
+        let push = |px: Real, py: Real, along: fn(Real, Real) -> Real, speed: Real| {
+            let (dx, dy) = (px - cx, py - cy);
+            let r = dx.hypot(dy);
+            if r == 0.0 || r >= radius {
+                0.0
+            } else {
+                speed * (1.0 - r / radius) * along(dx, dy) / r
+            }
+        };
+        for y in ys {
+            for x in xs.clone() {
+                if self.solid[y][x] {
+                    continue;
+                }
+                let cell = self.grid[y][x];
+                let speed = POKE_MACH * sound_speed(&cell, gamma);
+                if !speed.is_finite() {
+                    continue;
+                }
+                let (fx, fy) = (x as Real, y as Real);
+                let ((ux, uy), (vx, vy)) = match layout {
+                    Layout::Collocated => ((fx + 0.5, fy + 0.5), (fx + 0.5, fy + 0.5)),
+                    Layout::Staggered => ((fx + 1.0, fy + 0.5), (fx + 0.5, fy + 1.0)),
+                };
+                let cell = &mut self.grid[y][x];
+                cell.momentum_x += cell.density * push(ux, uy, |dx, _| dx, speed);
+                cell.momentum_y += cell.density * push(vx, vy, |_, dy| dy, speed);
+            }
+        }
+        for (x, y, internal) in internal {
+            if !self.solid[y][x] {
+                self.grid[y][x].energy = internal + kinetic(&self.grid, x, y);
+            }
+        }
+    }
+
+    // Zeroes every momentum, leaving the density where it is. With `keep_energy` the energy
+    // stays too, so the kinetic part turns into heat; otherwise it's dropped and the
+    // pressure comes out unchanged.
+    fn stop_motion(&mut self, keep_energy: bool) {
+        let centered = self.centered_grid();
+        for (row, centered_row) in self.grid.iter_mut().zip(&centered) {
+            for (cell, centered_cell) in row.iter_mut().zip(centered_row) {
+                if !keep_energy {
+                    cell.energy -= kinetic_energy(centered_cell);
+                }
+                cell.momentum_x = 0.0;
+                cell.momentum_y = 0.0;
+            }
+        }
+    }
+
+    // Re-derives every fluid cell's energy from its density and velocity and the pressure
+    // in `target`, as p / (gamma - 1) plus the kinetic part, so the three agree again
+    fn set_pressure(&mut self, target: &[Vec<Real>]) {
+        let gamma = self.settings.gamma;
+        let centered = self.centered_grid();
+        for (y, row) in self.grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                if self.solid[y][x] {
+                    continue;
+                }
+                let (vx, vy) = velocity(&centered[y][x]);
+                cell.energy = energy_from_primitives(cell.density, vx, vy, target[y][x], gamma);
+            }
+        }
+    }
+
+    // Puts the energy back in line with the pressure after the scheme has mangled it. With
+    // `flatten` every cell gets the ambient pressure; otherwise each keeps its own, and only
+    // cells whose pressure is negative or not a number get the ambient one. Non-finite
+    // momentum is zeroed first so the kinetic part is a number. Returns how many cells got
+    // the ambient pressure.
+    fn repair_pressure(&mut self, flatten: bool) -> usize {
+        for cell in self.grid.iter_mut().flatten() {
+            if !(cell.momentum_x.is_finite() && cell.momentum_y.is_finite()) {
+                cell.momentum_x = 0.0;
+                cell.momentum_y = 0.0;
+            }
+        }
+        let gamma = self.settings.gamma;
+        let ambient = calculate_pressure(&self.settings.ambient(), gamma);
+        let centered = self.centered_grid();
+        let mut replaced = 0;
+        let target: Vec<Vec<Real>> = centered
+            .iter()
+            .zip(&self.solid)
+            .map(|(row, solid_row)| {
+                row.iter()
+                    .zip(solid_row)
+                    .map(|(cell, &solid)| {
+                        let pressure = calculate_pressure(cell, gamma);
+                        if flatten || !(pressure > 0.0 && pressure.is_finite()) {
+                            replaced += usize::from(!solid);
+                            ambient
+                        } else {
+                            pressure
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.set_pressure(&target);
+        replaced
+    }
+
+    // The dt taken unless one is set by hand: the legacy scheme's CFL step over the whole
+    // grid, the finite volume one's share of the CFL limit or the acoustic one's share of
+    // its own, any of them scaled by the dt safety. The limit never goes past the old fixed
+    // step, so a field that's nearly still doesn't take one huge one.
+    fn automatic_dt(&self) -> Real {
+        let settings = &self.settings;
+        let dt = match settings.scheme {
+            Scheme::Legacy => {
+                let jet = self
+                    .jet_preview()
+                    .iter()
+                    .filter_map(|cell| crossing_time(cell, settings.gamma))
+                    .reduce(Real::min);
+                let limit = match (self.cfl_dt(), jet) {
+                    (Some(grid), Some(jet)) => Some(grid.min(jet)),
+                    (grid, jet) => grid.or(jet),
+                };
+                cfl_step(limit)
+            }
+            Scheme::FiniteVolume => cfl_step(self.cfl_dt()),
+            Scheme::Acoustic => finite_volume::FV_CFL * acoustic::cfl_dt(settings),
+        };
+        dt * self.dt_safety
+    }
+
+    // The jet cells moving the way the source is about to set them going, which it does
+    // before the fluxes run, so the step has to allow for it already. The legacy jet's
+    // velocity is a momentum, the others' a speed. Empty without a jet.
+    fn jet_preview(&self) -> Vec<Cell> {
+        let Some(source) = self.settings.source else {
+            return Vec::new();
+        };
+        let gamma = self.settings.gamma;
+        let cross_flow = source.cross_flow(self.sim_time).unwrap_or(0.0);
+        let jet_velocity = source.velocity_at(self.sim_time);
+        jet_rows(self.grid.len(), source.width, self.settings.symmetry)
+            .filter(|&y| !self.solid[y][0])
+            .map(|y| {
+                let (density, _, _, pressure) = primitives_from_cell(&self.grid[y][0], gamma);
+                let (vx, vy) = if self.settings.units == Units::Legacy && !source.conservative {
+                    (jet_velocity / density, cross_flow / density)
+                } else {
+                    (jet_velocity, cross_flow)
+                };
+                Cell::from_primitives(density, vx, vy, pressure, gamma)
+            })
+            .collect()
+    }
+
+    // The largest dt with a Courant number of 1 everywhere in the fluid, 1 / (|vx| + |vy| + c)
+    // at the fastest cell. Cells that have lost their density have no sound speed to go by
+    // and are left out. None if no cell has a finite signal speed.
+    fn cfl_dt(&self) -> Option<Real> {
+        let width = self.grid.width();
+        self.centered_grid()
+            .iter()
+            .flatten()
+            .zip(self.solid.iter().flatten())
+            .enumerate()
+            .filter(|(_, (_, &solid))| !solid)
+            .filter_map(|(i, (cell, _))| crossing_time(cell, self.gamma_at(i % width, i / width)))
+            .reduce(Real::min)
+    }
+
+    // The role of cell (x, y), from the same rows, edges and masks the source, the boundary
+    // pass and the sponge go by. An edge cell is an edge first, a jet cell the inflow.
+    fn region(&self, x: usize, y: usize) -> Region {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let settings = &self.settings;
+        // The legacy boundary pass runs even while the flux loop wraps the edges
+        let wraps = settings.periodic || (settings.wrap_edges && settings.scheme != Scheme::Legacy);
+        let wall = if settings.wall == WallKind::NoSlip {
+            Region::NoSlip
+        } else {
+            Region::Wall
+        };
+        if self.solid[y][x] {
+            return Region::Solid;
+        }
+        if let Some(source) = settings.source {
+            if x == 0 && jet_rows(height, source.width, settings.symmetry).contains(&y) {
+                return Region::Inflow;
+            }
+        }
+        if !wraps {
+            if x == 0 || x == width - 1 {
+                return Region::Outflow;
+            }
+            // The symmetry plane is a mirror too
+            if y == 0 || y == height - 1 {
+                return if settings.open_edges {
+                    Region::Outflow
+                } else {
+                    wall
+                };
+            }
+        }
+        if self
+            .grid
+            .neighbors(x, y, wraps)
+            .any(|(nx, ny)| self.solid[ny][nx])
+        {
+            return wall;
+        }
+        if self
+            .sponge
+            .as_ref()
+            .is_some_and(|sponge| sponge.rate(x, y, width, height) > 0.0)
+        {
+            return Region::Sponge;
+        }
+        Region::Interior
+    }
+
+    // The four sides of the update at fluid cell (x, y), left, right, up and down, for the
+    // stencil inspector. The finite volume and acoustic schemes' are their face fluxes. The
+    // legacy one's are each neighbor's share of the collocated update in calculate_fluxes,
+    // adding up to all of it but gravity and the work of the legacy forces: what comes in
+    // across the face, and the momentum from its pressure and density terms. None for solid
+    // cells, the edge cells the boundary pass sets, and the staggered layout, whose update
+    // doesn't split up by neighbor.
+    fn stencil(&self, x: usize, y: usize) -> Option<[Face; 4]> {
+        if self.solid[y][x] {
+            return None;
+        }
+        if self.settings.scheme == Scheme::FiniteVolume {
+            let gammas = self.gamma_field();
+            return Some(finite_volume::faces(
+                &self.grid,
+                &self.solid,
+                &self.settings,
+                gammas.as_deref(),
+                x,
+                y,
+            ));
+        }
+        if self.settings.scheme == Scheme::Acoustic {
+            return Some(acoustic::faces(
+                &self.grid,
+                &self.solid,
+                &self.settings,
+                x,
+                y,
+            ));
+        }
+        if self.settings.layout == Layout::Staggered {
+            return None;
+        }
+        let periodic = self.settings.periodic || self.settings.wrap_edges;
+        let (width, bottom) = (self.grid.width(), self.grid.height() - 1);
+        let stencil = match self.grid.stencil(x, y, periodic) {
+            None if self.settings.symmetry && y == bottom && x > 0 && x < width - 1 => {
+                Some(Stencil {
+                    left: x - 1,
+                    right: x + 1,
+                    up: y - 1,
+                    down: y,
+                })
+            }
+            stencil => stencil,
+        }?;
+        let gamma = self.settings.gamma;
+        let center = self.grid[y][x];
+        let pressure = calculate_pressure(&center, gamma);
+        let density_term = if self.settings.density_flux { 1.0 } else { 0.0 };
+        let wall_row =
+            |ny: usize| !periodic && (ny == 0 || (ny == bottom && !self.settings.symmetry));
+        // The left and top neighbors push the cell away from them, the others back. Walls
+        // and obstacles show the cell its mirror image, as in calculate_fluxes, which pushes
+        // with the cell's own pressure and density.
+        let face = |nx: usize, ny: usize, sign: Real, vertical: bool| {
+            let wall = self.solid[ny][nx] || (nx, ny) == (x, y) || (vertical && wall_row(ny));
+            let across = if wall {
+                mirrored(&center, vertical)
+            } else {
+                self.grid[ny][nx]
+            };
+            let scale = if vertical { VERTICAL_FLUX_SCALE } else { 1.0 };
+            let push = sign
+                * (scale * calculate_pressure(&across, gamma) / 2.0
+                    + density_term * across.density * pressure / 4.0);
+            let (momentum_x, momentum_y) = if vertical { (0.0, push) } else { (push, 0.0) };
+            Face {
+                neighbor: (!wall).then_some((nx, ny)),
+                across,
+                flux: Cell {
+                    momentum_x,
+                    momentum_y,
+                    ..Cell::zero()
+                } - face_flux(&center, &across, vertical, -sign, gamma),
+            }
+        };
+        Some([
+            face(stencil.left, y, 1.0, false),
+            face(stencil.right, y, -1.0, false),
+            face(x, stencil.up, 1.0, true),
+            face(x, stencil.down, -1.0, true),
+        ])
+    }
+
+    // What the stencil inspector prints for a clicked cell: each neighbor's state and what
+    // it adds per unit time, then what a whole step of `dt` does to the cell, boundaries and
+    // all, from running it on a copy
+    fn report_stencil(&self, x: usize, y: usize, dt: Real) {
+        let center = self.grid[y][x];
+        println!(
+            "Stencil of ({}, {}): density {:.4e}, pressure {:.4e}",
+            x,
+            y,
+            center.density,
+            calculate_pressure(&center, self.gamma_at(x, y))
+        );
+        match self.stencil(x, y) {
+            Some(faces) => {
+                for (side, face) in ["left", "right", "up", "down"].iter().zip(&faces) {
+                    let (place, gamma) = match face.neighbor {
+                        Some((nx, ny)) => (format!("({}, {})", nx, ny), self.gamma_at(nx, ny)),
+                        None => ("boundary".to_string(), self.gamma_at(x, y)),
+                    };
+                    let flux = face.flux;
+                    println!(
+                        "  {:<5} {:<10} density {:.4e}, pressure {:.4e}: mass {:+.4e}, x-momentum {:+.4e}, y-momentum {:+.4e}, energy {:+.4e}",
+                        side,
+                        place,
+                        face.across.density,
+                        calculate_pressure(&face.across, gamma),
+                        flux.density,
+                        flux.momentum_x,
+                        flux.momentum_y,
+                        flux.energy
+                    );
+                }
+            }
+            None => println!("  no per-neighbor terms here"),
+        }
+        let mut grid = self.grid.clone();
+        let mut scratch = grid.clone();
+        let gammas = self.gamma_field();
+        advance(
+            &mut grid,
+            &mut scratch,
+            &self.solid,
+            &self.solid_fraction,
+            &self.settings,
+            gammas.as_deref(),
+            dt,
+        );
+        let change = grid[y][x] - center;
+        println!(
+            "  a step of dt = {:.3e} changes it by mass {:+.4e}, x-momentum {:+.4e}, y-momentum {:+.4e}, energy {:+.4e}",
+            dt, change.density, change.momentum_x, change.momentum_y, change.energy
+        );
+    }
+
+    // The state at (x, y) meters from the top left corner of the grid, y pointing down the
+    // screen. Bilinear between the four nearest cell centers, leaving out solid ones and
+    // holding the edge cells' values for the outer half cell unless the grid wraps, in
+    // whichever variables `sampling` says. None outside the grid, when all four are solid,
+    // or when the result isn't finite.
+    fn sample_at(&self, x: Real, y: Real, sampling: Sampling) -> Option<Primitives> {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let (fx, fy) = (x / CELL_LENGTH - 0.5, y / CELL_LENGTH - 0.5);
+        if !(-0.5..width as Real - 0.5).contains(&fx) || !(-0.5..height as Real - 0.5).contains(&fy)
+        {
+            return None;
+        }
+        let periodic = self.settings.periodic;
+        let (x0, x1, tx) = interpolation_cells(fx, width, periodic);
+        let (y0, y1, ty) = interpolation_cells(fy, height, periodic);
+
+        let mut sum = [0.0; 4];
+        let mut gamma = 0.0;
+        let mut total_weight = 0.0;
+        for (cx, cy, weight) in [
+            (x0, y0, (1.0 - tx) * (1.0 - ty)),
+            (x1, y0, tx * (1.0 - ty)),
+            (x0, y1, (1.0 - tx) * ty),
+            (x1, y1, tx * ty),
+        ] {
+            if self.solid[cy][cx] || weight == 0.0 {
+                continue;
+            }
+            let cell = centered(&self.grid, cx, cy, periodic);
+            let cell_gamma = self.gamma_at(cx, cy);
+            let values = match sampling {
+                Sampling::Primitive => {
+                    let (density, vx, vy, pressure) = primitives_from_cell(&cell, cell_gamma);
+                    [density, vx, vy, pressure]
+                }
+                Sampling::Conservative => {
+                    [cell.density, cell.momentum_x, cell.momentum_y, cell.energy]
+                }
+            };
+            for (total, value) in sum.iter_mut().zip(values) {
+                *total += weight * value;
+            }
+            gamma += weight * cell_gamma;
+            total_weight += weight;
+        }
+        if total_weight == 0.0 {
+            return None;
+        }
+        let [a, b, c, d] = sum.map(|total| total / total_weight);
+        let (density, velocity_x, velocity_y, pressure) = match sampling {
+            Sampling::Primitive => (a, b, c, d),
+            Sampling::Conservative => {
+                let blend = Cell {
+                    density: a,
+                    momentum_x: b,
+                    momentum_y: c,
+                    energy: d,
+                };
+                primitives_from_cell(&blend, gamma / total_weight)
+            }
+        };
+        let primitives = Primitives {
+            density,
+            velocity_x,
+            velocity_y,
+            pressure,
+        };
+        [density, velocity_x, velocity_y, pressure]
+            .iter()
+            .all(|v| v.is_finite())
+            .then_some(primitives)
+    }
+
+    // Every cell within `radius` of (x, y) made solid or fluid. Nothing happens if they
+    // all already are, so a stroke can call it on every mouse move. A cell turning solid
+    // is reset to still ambient gas, so no stale momentum is left inside the obstacle.
+    fn paint_solid(&mut self, x: usize, y: usize, radius: usize, solid: bool) {
+        let value = if solid { 1.0 } else { 0.0 };
+        let mut solid_fraction = self.solid_fraction.clone();
+        let mut changed = false;
+        let ys = y.saturating_sub(radius)..(y + radius + 1).min(self.grid.height());
+        let xs = x.saturating_sub(radius)..(x + radius + 1).min(self.grid.width());
+        for cy in ys {
+            for cx in xs.clone() {
+                let (dx, dy) = (cx.abs_diff(x), cy.abs_diff(y));
+                if dx * dx + dy * dy <= radius * radius && solid_fraction[cy][cx] != value {
+                    solid_fraction[cy][cx] = value;
+                    if solid {
+                        self.grid[cy][cx] = self.settings.ambient();
+                    }
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.set_obstacle(solid_fraction);
+        }
+    }
+
+    // Back to t = 0 from `grid` and `tracers`, keeping the obstacle as it is now. Cells
+    // painted solid since take the ambient state like paint_solid leaves them, and the
+    // solid heats back to ambient too. The max hold, flow meter totals, mass balance and
+    // wake signal start over with it; the reference and allocation checks keep their worst
+    // step of the whole session.
+    fn restart(&mut self, grid: &[Vec<Cell>], tracers: &[TracerField]) {
+        let ambient = self.settings.ambient();
+        for ((row, start_row), solid_row) in self.grid.iter_mut().zip(grid).zip(&self.solid) {
+            for ((cell, start), &solid) in row.iter_mut().zip(start_row).zip(solid_row) {
+                *cell = if solid { ambient } else { *start };
+            }
+        }
+        for (tracer, start) in self.tracers.iter_mut().zip(tracers) {
+            tracer.concentration.clone_from(&start.concentration);
+        }
+        let ambient_temperature = temperature(&ambient, self.settings.gamma);
+        for t in self.solid_temperature.iter_mut().flatten() {
+            *t = ambient_temperature;
+        }
+        self.step_count = 0;
+        self.sim_time = 0.0;
+        if let Some(mut hold) = self.max_hold.take() {
+            hold.reset(self);
+            self.max_hold = Some(hold);
+        }
+        if let Some(meter) = &mut self.flow_meter {
+            meter.restart();
+        }
+        if self.mass_balance.is_some() {
+            self.mass_balance = Some(MassBalance::new());
+        }
+        if let Some(wake) = &mut self.wake {
+            wake.restart();
+        }
+    }
 
-const AIR_DENSITY: f64 = 1.225; // kg/m^3
-const GAMMA_AIR: f64 = 1.4; // Adiabatic index for air
-const ATMOSPHERIC_PRESSURE: f64 = 101325.0; // Pa
-const AIR_ENERGY: f64 = 1000.0; // Initial energy (example)
+    // Whether (x, y) is an edge cell the legacy update skips, leaving it to the boundary
+    // pass to copy from inside
+    fn skips_edge(&self, x: usize, y: usize) -> bool {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        self.settings.scheme == Scheme::Legacy
+            && !self.settings.periodic
+            && (x == 0 || y == 0 || x + 1 == width || y + 1 == height)
+    }
 
-#[derive(Debug, Clone)]
-struct Cell {
-    density: f64,
-    momentum_x: f64,
-    momentum_y: f64,
-    energy: f64,
-}
+    // The grid with every momentum at the cell center, whatever the layout
+    fn centered_grid(&self) -> Vec<Vec<Cell>> {
+        match self.settings.layout {
+            Layout::Collocated => self.grid.clone(),
+            Layout::Staggered => (0..self.grid.len())
+                .map(|y| {
+                    (0..self.grid[0].len())
+                        .map(|x| centered(&self.grid, x, y, self.settings.periodic))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
 
-fn render_grid(canvas: &mut Canvas<Window>, grid: &Vec<Vec<Cell>>, solid: &Vec<Vec<bool>>) {
-    for (y, row) in grid.iter().enumerate() {
-        for (x, cell) in row.iter().enumerate() {
-            if solid[y][x] {
-                canvas.set_draw_color(Color::RGB(100, 100, 100)); // Solid objects as gray
-            } else {
-                let density_color = (cell.density * 25.5).min(255.0) as u8;
-                canvas.set_draw_color(Color::RGB(density_color, density_color, 255));
+    fn reached_stop(&self) -> bool {
+        self.stop_at.is_some_and(|stop| self.sim_time >= stop)
+    }
+
+    // Solid cells relax toward the temperature of their 4-neighbors: fluid neighbors heat
+    // the surface, solid neighbors spread that heat inward. The fluid doesn't lose the heat.
+    fn conduct_heat(&mut self, dt: Real) {
+        if let Some(capacity) = self.settings.solid_heat_capacity {
+            self.exchange_heat(capacity, dt);
+            return;
+        }
+        let width = self.grid[0].len();
+        let height = self.grid.len();
+        // Explicit diffusion is only stable while each cell moves at most 1/4 of the way
+        let rate = (self.settings.conduction * dt).min(0.25);
+        let old = self.solid_temperature.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                if !self.solid[y][x] {
+                    continue;
+                }
+                let mut change = 0.0;
+                for (nx, ny) in self.solid.neighbors(x, y, self.settings.periodic) {
+                    let neighbor = if self.solid[ny][nx] {
+                        old[ny][nx]
+                    } else {
+                        temperature(&self.grid[ny][nx], self.settings.gamma)
+                    };
+                    if neighbor.is_finite() {
+                        change += neighbor - old[y][x];
+                    }
+                }
+                self.solid_temperature[y][x] = old[y][x] + rate * change;
             }
-            let rect = Rect::new((x as i32) * 10, (y as i32) * 10, 10, 10);
-            let _ = canvas.fill_rect(rect);
         }
     }
-}
-
-fn initialize_grid(width: usize, height: usize) -> Vec<Vec<Cell>> {
-    let mut grid = vec![
-        vec![
-            Cell {
-                density: AIR_DENSITY,
-                momentum_x: 0.0,
-                momentum_y: 0.0,
-                energy: AIR_ENERGY,
-            };
-            width
-        ];
-        height
-    ];
 
-    // Set a high-pressure region in the center for initial movement
-    let center_x = width / 2;
-    let center_y = height / 2;
-    grid[center_y][center_x].density = 10.0;
-    grid[center_y][center_x].energy = 1000.0; // High energy in the center
+    // Conduction with the heat accounted for: across every face of a solid cell flows
+    // conduction * dt * the temperature difference, heating the solid by that over its
+    // capacity and, at the surface, taking it out of the gas's energy. Each face's flow is
+    // capped at a quarter of what would even out the cells on either side, for stability.
+    fn exchange_heat(&mut self, capacity: Real, dt: Real) {
+        let conductance = self.settings.conduction * dt;
+        let gamma = self.settings.gamma;
+        let old = self.solid_temperature.clone();
+        let mut heat_lost = vec![vec![0.0; self.grid.width()]; self.grid.height()];
 
-    grid
-}
+        for (y, row) in self.solid.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|(_, &solid)| solid) {
+                let mut heat = 0.0;
+                for (nx, ny) in self.solid.neighbors(x, y, self.settings.periodic) {
+                    if self.solid[ny][nx] {
+                        heat += conductance.min(0.25 * capacity) * (old[ny][nx] - old[y][x]);
+                        continue;
+                    }
+                    let gas = &self.grid[ny][nx];
+                    let gas_capacity = gas.density / (gamma - 1.0);
+                    let difference = temperature(gas, gamma) - old[y][x];
+                    if !difference.is_finite() || gas_capacity <= 0.0 {
+                        continue;
+                    }
+                    let flow =
+                        conductance.min(0.25 * capacity).min(0.25 * gas_capacity) * difference;
+                    heat += flow;
+                    heat_lost[ny][nx] += flow;
+                }
+                self.solid_temperature[y][x] = old[y][x] + heat / capacity;
+            }
+        }
+        for (row, lost_row) in self.grid.iter_mut().zip(&heat_lost) {
+            for (cell, &lost) in row.iter_mut().zip(lost_row) {
+                cell.energy -= lost;
+            }
+        }
+    }
 
-fn calculate_pressure(cell: &Cell, gamma: f64) -> f64 {
-    let kinetic_energy = (cell.momentum_x.powi(2) + cell.momentum_y.powi(2)) / (2.0 * cell.density);
-    let pressure = (gamma - 1.0) * (cell.energy - kinetic_energy);
-    pressure
-}
+    // Mean temperature of the obstacle's cells, None without any
+    fn mean_solid_temperature(&self) -> Option<Real> {
+        let (sum, count) = self
+            .solid_temperature
+            .iter()
+            .flatten()
+            .zip(self.solid.iter().flatten())
+            .filter(|(_, &solid)| solid)
+            .fold((0.0, 0), |(sum, count), (&t, _)| (sum + t, count + 1));
+        (count > 0).then(|| sum / count as Real)
+    }
 
-fn calculate_fluxes(grid: &Vec<Vec<Cell>>, solid: &Vec<Vec<bool>>, gamma: f64) -> Vec<Vec<Cell>> {
-    let mut new_grid = grid.clone();
+    // The solid cell touching fluid with the highest temperature, as (x, y, temperature)
+    fn hottest_surface_cell(&self) -> Option<(usize, usize, Real)> {
+        let width = self.grid[0].len();
+        let height = self.grid.len();
+        let mut hottest: Option<(usize, usize, Real)> = None;
 
-    for y in 1..grid.len() - 1 {
-        for x in 1..grid[0].len() - 1 {
-            if solid[y][x] {
-                continue;
+        for y in 0..height {
+            for x in 0..width {
+                let on_surface = self.solid[y][x]
+                    && self
+                        .solid
+                        .neighbors(x, y, self.settings.periodic)
+                        .any(|(nx, ny)| !self.solid[ny][nx]);
+                let t = self.solid_temperature[y][x];
+                if on_surface && hottest.is_none_or(|(_, _, max)| t > max) {
+                    hottest = Some((x, y, t));
+                }
             }
+        }
 
-            // Calculate pressure at current cell and its neighbors
-            let pressure = calculate_pressure(&grid[y][x], gamma);
-            let pressure_left = calculate_pressure(&grid[y][x - 1], gamma);
-            let pressure_right = calculate_pressure(&grid[y][x + 1], gamma);
-            let pressure_up = calculate_pressure(&grid[y - 1][x], gamma);
-            let pressure_down = calculate_pressure(&grid[y + 1][x], gamma);
-
-            // Calculate pressure gradients
-            let pressure_flux_x = (pressure_right - pressure_left) / 2.0;
-            let pressure_flux_y = 0.1 * (pressure_down - pressure_up) / 2.0; // Reduce vertical flux
+        hottest
+    }
 
-            // Calculate density flux for stability
-            let density_flux_x = (grid[y][x + 1].density - grid[y][x - 1].density) / 2.0;
-            let density_flux_y = (grid[y + 1][x].density - grid[y - 1][x].density) / 2.0;
+    fn report_solid_temperature(&self) {
+        if let Some((x, y, t)) = self.hottest_surface_cell() {
+            println!(
+                "Hottest obstacle surface cell: ({}, {}) at T = {:.2}, obstacle mean T = {:.2}",
+                x,
+                y,
+                t,
+                self.mean_solid_temperature().unwrap_or(Real::NAN)
+            );
+        }
+    }
 
-            // Apply pressure flux to momentum to maintain movement
-            new_grid[y][x].momentum_x -= pressure_flux_x + density_flux_x * pressure / 2.0;
-            new_grid[y][x].momentum_y -= pressure_flux_y + density_flux_y * pressure / 2.0;
+    // Reference sizes for force coefficients: the wetted perimeter, as the number of faces
+    // between a solid and a fluid cell, and the frontal area, as the number of rows the
+    // obstacle shows up in (a 2D body's height facing the flow along x). Over a symmetry
+    // plane these are for the whole mirrored body.
+    fn obstacle_geometry(&self) -> (usize, usize) {
+        let periodic = self.settings.periodic;
+        let mut perimeter = 0;
+        for (y, row) in self.solid.iter().enumerate() {
+            for (x, &solid) in row.iter().enumerate() {
+                if solid {
+                    perimeter += self
+                        .solid
+                        .neighbors(x, y, periodic)
+                        .filter(|&(nx, ny)| !self.solid[ny][nx])
+                        .count();
+                }
+            }
+        }
+        let frontal_area = self.solid.iter().filter(|row| row.contains(&true)).count();
+        if self.settings.symmetry {
+            (2 * perimeter, 2 * frontal_area)
+        } else {
+            (perimeter, frontal_area)
+        }
+    }
 
-            // Calculate average density and energy to update the cell
-            let avg_density = (grid[y][x].density
-                + grid[y - 1][x].density
-                + grid[y + 1][x].density
-                + grid[y][x - 1].density
-                + grid[y][x + 1].density)
-                / 5.0;
+    // Pressure force of the fluid on the obstacle, summed over the faces between solid and
+    // fluid cells, as (drag, lift): drag along +x, with the flow, and lift up the screen.
+    // Friction on no-slip walls isn't included. Over a symmetry plane this is for the whole
+    // mirrored body, whose lift cancels out.
+    fn obstacle_force(&self) -> (Real, Real) {
+        let grid = self.centered_grid();
+        let (periodic, gamma) = (self.settings.periodic, self.settings.gamma);
+        let (mut fx, mut fy) = (0.0, 0.0);
+        for (y, row) in self.solid.iter().enumerate() {
+            for (x, &solid) in row.iter().enumerate() {
+                if !solid {
+                    continue;
+                }
+                for (nx, ny) in self.solid.neighbors(x, y, periodic) {
+                    if self.solid[ny][nx] {
+                        continue;
+                    }
+                    // Pushes away from the fluid neighbor, into the solid. On a periodic
+                    // grid a wrapped neighbor is on the far side of the index.
+                    let pressure = calculate_pressure(&grid[ny][nx], gamma);
+                    let dx = if nx == x {
+                        0
+                    } else if (nx + 1) % row.len() == x {
+                        1
+                    } else {
+                        -1
+                    };
+                    let dy = if ny == y {
+                        0
+                    } else if (ny + 1) % self.solid.len() == y {
+                        1
+                    } else {
+                        -1
+                    };
+                    fx += pressure * dx as Real;
+                    fy += pressure * dy as Real;
+                }
+            }
+        }
+        if self.settings.symmetry {
+            (2.0 * fx, 0.0)
+        } else {
+            (fx, -fy)
+        }
+    }
 
-            let avg_energy = (grid[y][x].energy
-                + grid[y - 1][x].energy
-                + grid[y + 1][x].energy
-                + grid[y][x - 1].energy
-                + grid[y][x + 1].energy)
-                / 5.0;
+    // Circulation around the obstacle, the vorticity integrated over its bounding box grown
+    // by CIRCULATION_MARGIN cells. Unlike compute_vorticity this counts the solid cells too:
+    // the velocity dropping to zero at the wall is the vortex sheet carrying the bound
+    // circulation, and with it the central differences telescope to the line integral of
+    // the velocity around the box. Clockwise on screen is positive. Over a symmetry plane
+    // it's for the simulated half, the mirrored one cancelling it. None without an
+    // obstacle.
+    fn circulation(&self) -> Option<Real> {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (width, 0, height, 0);
+        for (y, row) in self.solid.iter().enumerate() {
+            for (x, _) in row.iter().enumerate().filter(|(_, &solid)| solid) {
+                (min_x, max_x) = (min_x.min(x), max_x.max(x));
+                (min_y, max_y) = (min_y.min(y), max_y.max(y));
+            }
+        }
+        if min_x > max_x {
+            return None;
+        }
+        // One cell in from the edges, so every cell in the box has all four neighbors
+        let xs = min_x.saturating_sub(CIRCULATION_MARGIN).max(1)
+            ..=(max_x + CIRCULATION_MARGIN).min(width - 2);
+        let ys = min_y.saturating_sub(CIRCULATION_MARGIN).max(1)
+            ..=(max_y + CIRCULATION_MARGIN).min(height - 2);
 
-            // Update density and energy based on calculated fluxes
-            new_grid[y][x].density = avg_density;
-            new_grid[y][x].energy = avg_energy;
+        let grid = self.centered_grid();
+        let velocity_at = |x: usize, y: usize| {
+            if self.solid[y][x] {
+                (0.0, 0.0)
+            } else {
+                velocity(&grid[y][x])
+            }
+        };
+        let mut circulation = 0.0;
+        for y in ys {
+            for x in xs.clone() {
+                let (_, v_right) = velocity_at(x + 1, y);
+                let (_, v_left) = velocity_at(x - 1, y);
+                let (u_down, _) = velocity_at(x, y + 1);
+                let (u_up, _) = velocity_at(x, y - 1);
+                circulation += (v_right - v_left) / 2.0 - (u_down - u_up) / 2.0;
+            }
         }
+        Some(circulation)
     }
 
-    new_grid
-}
+    // The circulation, and next to the lift from the pressure the Kutta-Joukowski lift it
+    // implies, rho U circulation, with the ambient density and the jet's speed as the free
+    // stream. The two only agree for a steady flow well past the obstacle.
+    fn report_circulation(&self) {
+        let Some(circulation) = self.circulation() else {
+            println!("Circulation: no obstacle");
+            return;
+        };
+        print!(
+            "Circulation around the obstacle: {:.4e} (clockwise)",
+            circulation
+        );
+        if let Some(source) = self.settings.source {
+            let density = self.settings.ambient().density;
+            let (_, lift) = self.obstacle_force();
+            print!(
+                ", Kutta-Joukowski lift {:.4e} against {:.4e} from the pressure",
+                density * source.velocity_at(self.sim_time) * circulation,
+                lift
+            );
+        }
+        println!();
+    }
 
-fn update_grid(grid: &mut Vec<Vec<Cell>>, new_grid: Vec<Vec<Cell>>) {
-    *grid = new_grid;
+    fn report_obstacle_geometry(&self) {
+        match self.obstacle_geometry() {
+            (_, 0) => println!("Obstacle: none"),
+            (perimeter, frontal_area) => println!(
+                "Obstacle: wetted perimeter {} faces, frontal area {} cells",
+                perimeter, frontal_area
+            ),
+        }
+    }
 }
 
-fn add_fluid_source(
+// The solver part of a step, without any sources. Returns how many updates were clamped.
+// `scratch` is a second grid-sized buffer that gets reused instead of allocating every step
+fn advance(
     grid: &mut Vec<Vec<Cell>>,
-    width: usize,
-    emission_rate: f64,
-    fluid_velocity: f64,
-) {
-    let height = grid.len();
-    let center_y = height / 2;
-
-    for y in center_y - width / 2..center_y + width / 2 {
-        grid[y][0].density += emission_rate;
-        grid[y][0].momentum_x = fluid_velocity;
-        grid[y][0].energy += AIR_ENERGY + 100.0;
+    scratch: &mut Vec<Vec<Cell>>,
+    solid: &[Vec<bool>],
+    solid_fraction: &[Vec<Real>],
+    settings: &Settings,
+    gammas: Option<&[Vec<Real>]>, // Per cell, only the finite volume scheme takes them
+    dt: Real,
+) -> usize {
+    // Update the fluid grid using the new flux calculations
+    let mut clamps = 0;
+    match settings.scheme {
+        Scheme::Legacy => {
+            clamps += calculate_fluxes(grid, scratch, solid, solid_fraction, settings, dt)
+        }
+        Scheme::FiniteVolume => finite_volume::calculate_fluxes(
+            grid,
+            scratch,
+            solid,
+            solid_fraction,
+            settings,
+            gammas,
+            dt,
+        ),
+        Scheme::Acoustic => {
+            acoustic::calculate_fluxes(grid, scratch, solid, solid_fraction, settings, dt)
+        }
+    }
+    if !settings.periodic && settings.scheme == Scheme::Legacy {
+        apply_boundary_conditions(scratch, solid, settings);
+    }
+    if settings.wall == WallKind::NoSlip {
+        clamps += apply_wall_friction(
+            scratch,
+            solid_fraction,
+            settings.friction * dt,
+            settings.periodic,
+        );
+    }
+    if settings.relaxation < 1.0 {
+        relax(grid, scratch, settings.relaxation);
     }
+    update_grid(grid, scratch);
+    clamps
 }
 
-fn create_solid_object(width: usize, height: usize) -> Vec<Vec<bool>> {
-    let mut solid_object = vec![vec![false; width]; height];
-
-    for y in 20..40 {
-        for x in 30..50 {
-            solid_object[y][x] = true;
+// Takes every cell of the updated grid only `alpha` of the way from the old one:
+// U_new = U_old + alpha (U_updated - U_old)
+fn relax(old: &[Vec<Cell>], updated: &mut [Vec<Cell>], alpha: Real) {
+    for (row, old_row) in updated.iter_mut().zip(old) {
+        for (cell, &old) in row.iter_mut().zip(old_row) {
+            *cell = old + (*cell - old) * alpha;
         }
     }
-
-    solid_object
 }
 
-fn apply_boundary_conditions(grid: &mut Vec<Vec<Cell>>) {
+// Fluid cells touching an obstacle lose a fraction `amount` of their momentum along the
+// surface: a solid cell left or right means the surface runs vertically, so momentum_y is
+// damped, and likewise for above or below. A partly covered neighbor only applies its share
+// of the friction. Clamped so the flow stops instead of reversing; returns how often that
+// happened.
+fn apply_wall_friction(
+    grid: &mut [Vec<Cell>],
+    solid_fraction: &[Vec<Real>],
+    amount: Real,
+    periodic: bool,
+) -> usize {
     let width = grid[0].len();
     let height = grid.len();
+    let mut clamps = 0;
+    let mut damping = |wall: Real| {
+        let factor = 1.0 - amount * wall;
+        if factor < 0.0 {
+            clamps += 1;
+        }
+        factor.max(0.0)
+    };
 
-    // Zero out vertical momentum at left and right boundaries
     for y in 0..height {
-        grid[y][0].momentum_y = 0.0; // Left boundary
-        grid[y][width - 1].momentum_y = 0.0; // Right boundary
-
-        // Optional: Zero out vertical movement at the boundaries
-        grid[y][0].momentum_x = grid[y][1].momentum_x; // Maintain horizontal velocity
-        grid[y][width - 1].momentum_x = grid[y][width - 2].momentum_x; // Similar velocity at boundary
-    }
-
-    // Apply conditions at the top and bottom boundaries
-    for x in 0..width {
-        grid[0][x].momentum_y = 0.0; // Top boundary
-        grid[height - 1][x].momentum_y = 0.0; // Bottom boundary
-
-        // Optional: Match density and energy at the boundaries to prevent pressure imbalances
-        grid[0][x].density = grid[1][x].density; // Match density at top
-        grid[height - 1][x].density = grid[height - 2][x].density; // Match density at bottom
-
-        grid[0][x].energy = grid[1][x].energy; // Match energy at top
-        grid[height - 1][x].energy = grid[height - 2][x].energy; // Match energy at bottom
+        for x in 0..width {
+            if solid_fraction[y][x] >= 1.0 {
+                continue;
+            }
+            let (mut vertical_wall, mut horizontal_wall): (Real, Real) = (0.0, 0.0);
+            for (nx, ny) in solid_fraction.neighbors(x, y, periodic) {
+                let cover = solid_fraction[ny][nx];
+                if ny == y {
+                    vertical_wall = vertical_wall.max(cover);
+                } else {
+                    horizontal_wall = horizontal_wall.max(cover);
+                }
+            }
+            let cell = &mut grid[y][x];
+            if vertical_wall > 0.0 {
+                cell.momentum_y *= damping(vertical_wall);
+            }
+            if horizontal_wall > 0.0 {
+                cell.momentum_x *= damping(horizontal_wall);
+            }
+        }
     }
+    clamps
 }
 
-fn create_airfoil(width: usize, height: usize) -> Vec<Vec<bool>> {
-    let mut airfoil = vec![vec![false; width]; height];
+fn run_headless(
+    options: &Options,
+    sim: &mut Simulation,
+    scenario: &Scenario,
+    view: &View,
+    mut recorder: Option<&mut Recorder>,
+    logs: &mut Logs,
+    mut steady: Option<&mut SteadyState>,
+) -> Result<(), AppError> {
+    let mut blast_probe = options
+        .blast_probe
+        .map(|threshold| BlastProbe::new(scenario.blast_center(), threshold));
+    let mut checkerboard = options.checkerboard.then(CheckerboardMonitor::new);
+    let mut breakpoints = Breakpoints::new(&options.break_on, sim);
+    // What the tracers get compared against after the flow has been run back
+    let start_tracers = options.reverse_at.map(|_| sim.tracers.clone());
+    let mut reverse_at = options.reverse_at;
+    let mut warned_unstable = false;
+    let mut warned_supersonic = false;
+    let mut stall = StallWatch::new();
+    let started = Instant::now();
+    let first_step = sim.step_count;
+    let mut timed_out = false;
+    // Scripted obstacle changes edit a copy, the same as Tab does in the window
+    let mut scenario = scenario.clone();
+    let mut script = Script::new(&scenario.events);
+    script.run(sim, &mut scenario, view)?;
 
-    // Create a simple tear-drop airfoil
-    for y in 0..height {
-        for x in 0..width {
-            let x_f = x as f64 / width as f64;
-            let y_f = y as f64 / height as f64;
-            let distance = ((x_f - 0.5).powi(2) + (y_f - 0.5).powi(2)).sqrt();
-            if distance < 0.25 {
-                airfoil[y][x] = true;
+    loop {
+        let done = match sim.stop_at {
+            Some(_) => sim.reached_stop(),
+            None => sim.step_count >= options.steps as u64,
+        };
+        if done {
+            break;
+        }
+        if options
+            .max_seconds
+            .is_some_and(|limit| started.elapsed().as_secs_f64() > limit)
+        {
+            timed_out = true;
+            break;
+        }
+        let report = sim.step(None);
+        if !report.is_stable() && !warned_unstable {
+            report.warn_unstable(sim.step_count);
+            warned_unstable = true;
+        }
+        if report.is_supersonic() && !warned_supersonic {
+            report.warn_supersonic(sim.step_count);
+            warned_supersonic = true;
+        }
+        stall.record(sim, &report);
+        logs.record(sim, &report)?;
+        if let Some(probe) = &mut blast_probe {
+            probe.record(sim);
+        }
+        if let Some(steady) = steady.as_deref_mut() {
+            if steady.record(sim, report.dt) && steady.pause {
+                break;
             }
         }
+        if let Some(monitor) = &mut checkerboard {
+            monitor.record(sim);
+        }
+        script.run(sim, &mut scenario, view)?;
+        if let Some(recorder) = recorder.as_deref_mut() {
+            recorder.capture(sim, view).context("Recording failed")?;
+        }
+        if breakpoints.check(sim, &report) {
+            break;
+        }
+        if reverse_at.is_some_and(|time| sim.sim_time >= time) {
+            reverse_at = None;
+            sim.reverse();
+            println!(
+                "Reversed the flow at step {}, t = {:.4}",
+                sim.step_count, sim.sim_time
+            );
+        }
+    }
+    println!("Ran {} steps, t = {:.4}", sim.step_count, sim.sim_time);
+    if options.benchmark {
+        // Everything a step does counts, the monitors and logs asked for included
+        let seconds = started.elapsed().as_secs_f64();
+        let steps = sim.step_count - first_step;
+        let cells = (sim.grid.width() * sim.grid.height()) as f64;
+        let threads = rayon::current_num_threads();
+        println!(
+            "Benchmark: {} steps in {:.3} s, {:.1} steps/s, {:.3e} cell updates/s on {} {}",
+            steps,
+            seconds,
+            steps as f64 / seconds,
+            steps as f64 * cells / seconds,
+            threads,
+            if threads == 1 { "thread" } else { "threads" }
+        );
+    }
+    script.report();
+    if let Some(start) = &start_tracers {
+        sim.report_tracer_departure(start);
+    }
+
+    if let Some(probe) = &blast_probe {
+        probe.report();
+    }
+    if let Some(monitor) = &checkerboard {
+        monitor.report();
+    }
+    if options.circulation {
+        sim.report_circulation();
     }
 
-    airfoil
+    if sim.settings.conduction > 0.0 {
+        sim.report_solid_temperature();
+    }
+    if timed_out {
+        return Err(AppError(format!(
+            "gave up after {:.1} s of wall-clock time (--max-seconds), at step {}, t = {:.4}",
+            started.elapsed().as_secs_f64(),
+            sim.step_count,
+            sim.sim_time
+        )));
+    }
+    Ok(())
 }
 
-fn main() {
-    let sdl_context: Sdl = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+// Runs until the window is closed, returning the view that was on screen at the end
+fn run_window(
+    sim: &mut Simulation,
+    scenario: &Scenario,
+    mut view: View,
+    mut recorder: Option<&mut Recorder>,
+    logs: &mut Logs,
+    mut steady: Option<&mut SteadyState>,
+    break_on: &[BreakCondition],
+) -> Result<View, AppError> {
+    let sdl_context: Sdl = sdl2::init().context("Failed to initialize SDL (is SDL2 installed?)")?;
+    let video_subsystem = sdl_context
+        .video()
+        .context("Failed to initialize video (is a display available? try --headless)")?;
+    // Rows on screen, twice the grid's with the mirror image drawn underneath
+    let rows = sim.grid.len() as u32 * if view.mirror { 2 } else { 1 };
     let window = video_subsystem
-        .window("Euler Fluid Simulation with Airfoil", 800, 600)
+        .window(
+            WINDOW_TITLE,
+            sim.grid[0].len() as u32 * view.cell_size,
+            rows * view.cell_size,
+        )
         .position_centered()
+        .resizable()
         .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
-
-    // Increase the grid size
-    let grid_width = 80; // Increased width
-    let grid_height = 60; // Increased height
-    let mut grid = initialize_grid(grid_width, grid_height);
-    //let solid_object = create_airfoil(grid_width, grid_height); // simple oval cause idk how to make a tear drop/foil
-    let solid_object = create_solid_object(grid_width, grid_height); // just a block to prove my sim works
+        .context("Failed to create window")?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .context("Failed to create renderer")?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, sim.grid[0].len() as u32, rows)
+        .context("Failed to create the grid texture")?;
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut event_pump = sdl_context
+        .event_pump()
+        .context("Failed to get the SDL event pump")?;
 
     // FPS tracking variables
     let mut frame_count = 0;
     let mut last_fps_update = Instant::now();
-    let mut last_frame_time = Instant::now();
     let mut fps = 0.0;
 
+    // Manual dt set with -/=, None means the default step
+    let mut dt_override: Option<Real> = None;
+    let mut dt = sim.automatic_dt();
+    let mut clamps = 0; // In the last step, for D
+
+    // While paused, S takes a single step
+    let mut paused = false;
+    let mut step_once = false;
+    let mut breakpoints = Breakpoints::new(break_on, sim);
+    // Slow motion set with , and ., in real seconds per step; None steps every frame.
+    // Frames in between only redraw, `step_budget` counting up to the next step.
+    let mut slow_motion: Option<f64> = None;
+    let mut step_budget = 0.0;
+    let mut last_frame = Instant::now();
+    // Mouse position in window pixels
+    let mut cursor = (0, 0);
+    // The obstacle can only be painted in edit mode (O), a left-button stroke painting
+    // solid and a right-button one erasing. On the edges the legacy scheme doesn't update
+    // that's only noted the first time.
+    let mut editing = false;
+    let mut stroke: Option<bool> = None;
+    let mut noted_edges = false;
+    // How far from the cursor painting and pokes reach, set with the mouse wheel
+    let mut brush = 0;
+    // With the stencil inspector on (N) a left click picks the cell it shows
+    let mut inspecting = false;
+    // Q on two cells zooms into the rectangle between them, drawn over it until Q again
+    let mut zoom_corner: Option<(usize, usize)> = None;
+    let mut zoomed: Option<(Zoom, Simulation, Texture)> = None;
+    let mut warned_unstable = false;
+    let mut warned_supersonic = false;
+    let mut stall = StallWatch::new();
+    // B runs the flow backward, the tracers being compared with how they started each time
+    let mut reversed = false;
+    let start_tracers = sim.tracers.clone();
+    // R starts over from here, on the obstacle as it is by then, with the script and
+    // everything watching the run starting over too. The logs and the recording carry on.
+    let start_grid = sim.grid.clone();
+
+    // Tab swaps the obstacle for the next built-in one. Exports follow along, so the local
+    // copy of the scenario is what gets edited.
+    let mut scenario = scenario.clone();
+    let mut obstacle = Obstacle::Block;
+    let mut script = Script::new(&scenario.events);
+    script.run(sim, &mut scenario, &view)?;
+
     'running: loop {
+        let mut take_screenshot = false;
+        let mut title_dirty = false;
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -228,6 +3134,477 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => take_screenshot = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    paused = !paused;
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    view.mode = view.mode.next();
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::T),
+                    ..
+                } => view.solid_temperature = !view.solid_temperature,
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => view.cell_borders = !view.cell_borders,
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    ..
+                } => view.legend = !view.legend,
+                Event::KeyDown {
+                    keycode: Some(Keycode::U),
+                    ..
+                } => view.surface_cp = !view.surface_cp,
+                Event::KeyDown {
+                    keycode: Some(Keycode::J),
+                    ..
+                } => view.obstacle_outline = !view.obstacle_outline,
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => view.arrows = !view.arrows,
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => {
+                    view.histogram = HistogramField::cycle(view.histogram);
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::X),
+                    ..
+                } => {
+                    let field = HoldField::cycle(sim.max_hold.as_ref().map(|hold| hold.field));
+                    sim.max_hold = field.map(|field| MaxHold::new(field, sim));
+                    if field.is_some() {
+                        view.mode = RenderMode::MaxHold;
+                    }
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => {
+                    sim.reverse();
+                    reversed = !reversed;
+                    println!(
+                        "Reversed the flow at step {}, t = {:.4}",
+                        sim.step_count, sim.sim_time
+                    );
+                    sim.report_tracer_departure(&start_tracers);
+                    title_dirty = true;
+                }
+                // Into the tracer view, then through the tracers one at a time and back to all
+                Event::KeyDown {
+                    keycode: Some(Keycode::Y),
+                    ..
+                } => {
+                    if sim.tracers.is_empty() {
+                        println!("No tracers in this scenario");
+                    } else if view.mode != RenderMode::Tracers {
+                        view.mode = RenderMode::Tracers;
+                    } else {
+                        view.tracer = match view.tracer {
+                            None => Some(0),
+                            Some(i) if i + 1 < sim.tracers.len() => Some(i + 1),
+                            Some(_) => None,
+                        };
+                    }
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => step_once = paused,
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => diagnostics::print_state(sim, dt, clamps),
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    keymod,
+                    ..
+                } => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        if let Some(mut hold) = sim.max_hold.take() {
+                            hold.reset(sim);
+                            sim.max_hold = Some(hold);
+                        }
+                    } else {
+                        logs.restart(sim)?;
+                        sim.restart(&start_grid, &start_tracers);
+                        reversed = false;
+                        warned_unstable = false;
+                        warned_supersonic = false;
+                        stall = StallWatch::new();
+                        breakpoints = Breakpoints::new(break_on, sim);
+                        if let Some(steady) = steady.as_deref_mut() {
+                            steady.restart();
+                        }
+                        script = Script::new(&scenario.events);
+                        script.run(sim, &mut scenario, &view)?;
+                        println!("Restarted from t = 0, the logs and the recording carrying on");
+                        title_dirty = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::W),
+                    ..
+                } => {
+                    sim.settings.wrap_edges = !sim.settings.wrap_edges;
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    ..
+                } => {
+                    editing = !editing;
+                    stroke = None;
+                    title_dirty = true;
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: button @ (MouseButton::Left | MouseButton::Right),
+                    x,
+                    y,
+                    ..
+                } if editing => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    // Off the grid, over the black bars, there's nothing to paint
+                    if let Some((cx, cy)) = cell_under(window, sim, &view, (x, y)) {
+                        let paint = button == MouseButton::Left;
+                        stroke = Some(paint);
+                        sim.paint_solid(cx, cy, brush, paint);
+                        if !noted_edges && sim.skips_edge(cx, cy) {
+                            println!("The legacy scheme doesn't update the edge cells, the boundary pass copies them from inside");
+                            noted_edges = true;
+                        }
+                        // Exports carry the painted mask, which the shapes would draw over
+                        scenario.shapes.clear();
+                        scenario.obstacle_svg = None;
+                        scenario.obstacle_png = None;
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if inspecting => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    if let Some((cx, cy)) = cell_under(window, sim, &view, (x, y)) {
+                        view.stencil = Some((cx, cy));
+                        let dt = dt_override
+                            .or(sim.fixed_dt)
+                            .unwrap_or_else(|| sim.automatic_dt());
+                        sim.report_stencil(cx, cy, dt);
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left | MouseButton::Right,
+                    ..
+                } if stroke.is_some() => {
+                    stroke = None;
+                    sim.report_obstacle_geometry();
+                }
+                Event::MouseWheel { y, .. } => {
+                    brush = brush
+                        .saturating_add_signed(y as isize)
+                        .min(MAX_BRUSH_RADIUS);
+                    println!("Brush radius {} cells", brush);
+                    title_dirty = true;
+                }
+                Event::MouseMotion { x, y, .. } => {
+                    cursor = (x, y);
+                    if let Some(paint) = stroke {
+                        let window = canvas
+                            .output_size()
+                            .context("Failed to read the window size")?;
+                        if let Some((cx, cy)) = cell_under(window, sim, &view, cursor) {
+                            sim.paint_solid(cx, cy, brush, paint);
+                            if !noted_edges && sim.skips_edge(cx, cy) {
+                                println!("The legacy scheme doesn't update the edge cells, the boundary pass copies them from inside");
+                                noted_edges = true;
+                            }
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    if let Some((x, y)) = window_to_cell(window, sim, &view, cursor) {
+                        sim.poke(x, y, POKE_RADIUS.max(brush as Real));
+                        println!("Poked ({:.1}, {:.1})", x, y);
+                    }
+                }
+                // Shift samples both ways, for how much the choice matters at that point
+                Event::KeyDown {
+                    keycode: Some(Keycode::I),
+                    keymod,
+                    ..
+                } => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    if let Some((x, y)) = window_to_cell(window, sim, &view, cursor) {
+                        let (x, y) = (x * CELL_LENGTH, y * CELL_LENGTH);
+                        let samplings = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            vec![Sampling::Primitive, Sampling::Conservative]
+                        } else {
+                            vec![sim.sampling]
+                        };
+                        for sampling in samplings {
+                            match sim.sample_at(x, y, sampling) {
+                                Some(p) => println!(
+                                    "At ({:.2}, {:.2}) m, {}: density {:.4}, velocity ({:.4}, {:.4}), pressure {:.4}",
+                                    x, y, sampling.name(), p.density, p.velocity_x, p.velocity_y, p.pressure
+                                ),
+                                None => println!("At ({:.2}, {:.2}) m: no fluid state", x, y),
+                            }
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Z),
+                    keymod,
+                    ..
+                } => {
+                    let keep_energy = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    sim.stop_motion(keep_energy);
+                    if keep_energy {
+                        println!("Stopped all motion, its kinetic energy kept as heat");
+                    } else {
+                        println!("Stopped all motion");
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::K),
+                    keymod,
+                    ..
+                } => {
+                    let flatten = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let replaced = sim.repair_pressure(flatten);
+                    if flatten {
+                        println!("Reset the pressure everywhere to ambient");
+                    } else {
+                        println!(
+                            "Recomputed the energy from the pressure, {} bad cells set to ambient",
+                            replaced
+                        );
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    ..
+                } => sim.report_circulation(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => {
+                    let path = next_free_path("grid", "csv");
+                    match snapshot::save_csv(&sim.grid, &path) {
+                        Ok(()) => println!("Dumped grid to {}", path.display()),
+                        Err(e) => eprintln!("Grid dump failed: {}", e),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => {
+                    obstacle = obstacle.next();
+                    scenario.obstacle.clear();
+                    scenario.obstacle_svg = None;
+                    scenario.obstacle_png = None;
+                    scenario.shapes = obstacle.shapes();
+                    if !scenario.normalized {
+                        let (width, height) = (scenario.width, scenario.height);
+                        for shape in &mut scenario.shapes {
+                            *shape = shape.scaled(width, height);
+                        }
+                    }
+                    sim.set_obstacle(scenario.solid_fraction()?);
+                    println!("Obstacle: {}", obstacle.name());
+                    sim.report_obstacle_geometry();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => {
+                    let path = Path::new(SCENARIO_EXPORT_PATH);
+                    match scenario.capture(sim).save(path) {
+                        Ok(()) => println!("Exported scenario to {}", path.display()),
+                        Err(e) => eprintln!("Scenario export failed: {}", e),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    ..
+                } => {
+                    dt_override = Some(dt_override.unwrap_or(dt) / 2.0);
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    ..
+                } => {
+                    dt_override = Some(dt_override.unwrap_or(dt) * 2.0);
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => {
+                    sim.dt_safety /= DT_SAFETY_STEP;
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::RightBracket),
+                    ..
+                } => {
+                    sim.dt_safety = (sim.dt_safety * DT_SAFETY_STEP).min(1.0);
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    keymod,
+                    ..
+                } => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let viscosity = &mut sim.settings.viscosity;
+                        *viscosity = if *viscosity == 0.0 {
+                            VISCOSITY_START
+                        } else {
+                            *viscosity * VISCOSITY_STEP
+                        };
+                    } else {
+                        slow_motion =
+                            Some(slow_motion.map_or(SLOW_MOTION_START, |s| s * SLOW_MOTION_STEP));
+                    }
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Comma),
+                    keymod,
+                    ..
+                } => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let viscosity = sim.settings.viscosity / VISCOSITY_STEP;
+                        // Just under the start rounds back down to it
+                        sim.settings.viscosity = if viscosity < VISCOSITY_START * 0.999 {
+                            0.0
+                        } else {
+                            viscosity
+                        };
+                    } else {
+                        slow_motion = slow_motion
+                            .map(|s| s / SLOW_MOTION_STEP)
+                            .filter(|&s| s >= SLOW_MOTION_START);
+                    }
+                    title_dirty = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => {
+                    inspecting = !inspecting;
+                    if !inspecting {
+                        view.stencil = None;
+                    }
+                    title_dirty = true;
+                }
+                // Starts or stops a flow meter on the column under the cursor
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    match cell_under(window, sim, &view, cursor) {
+                        Some((0, _)) => {
+                            println!("The flow meter needs a column with one to its left")
+                        }
+                        Some((x, _)) => {
+                            let meter = sim.flow_meter.get_or_insert_with(|| FlowMeter::new(&[]));
+                            meter.toggle(x);
+                            if meter.is_empty() {
+                                sim.flow_meter = None;
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Q),
+                    ..
+                } => {
+                    let window = canvas
+                        .output_size()
+                        .context("Failed to read the window size")?;
+                    if zoomed.take().is_some() {
+                        title_dirty = true;
+                    } else if let Some(cell) = cell_under(window, sim, &view, cursor) {
+                        match zoom_corner.take() {
+                            None => {
+                                zoom_corner = Some(cell);
+                                println!(
+                                    "Zoom corner at ({}, {}), Q on the opposite one",
+                                    cell.0, cell.1
+                                );
+                            }
+                            Some(corner) => {
+                                let duration = ZOOM_STEPS
+                                    * dt_override
+                                        .or(sim.fixed_dt)
+                                        .unwrap_or_else(|| sim.automatic_dt());
+                                let zoom = Zoom::between(corner, cell, ZOOM_FACTOR, duration);
+                                match zoom.problem(sim.grid.width(), sim.grid.height()) {
+                                    Some(problem) => println!("Can't zoom: {}", problem),
+                                    None => {
+                                        let region = zoom.run(sim);
+                                        zoom.report(sim, &region);
+                                        let texture = texture_creator
+                                            .create_texture_streaming(
+                                                PixelFormatEnum::RGB24,
+                                                region.grid.width() as u32,
+                                                region.grid.height() as u32,
+                                            )
+                                            .context("Failed to create the zoom texture")?;
+                                        zoomed = Some((zoom, region, texture));
+                                    }
+                                }
+                            }
+                        }
+                        title_dirty = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num0),
+                    ..
+                } => {
+                    dt_override = None; // Back to the default step
+                    title_dirty = true;
+                }
                 _ => {}
             }
         }
@@ -236,28 +3613,157 @@ fn main() {
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
 
-        // Add a fluid source on the left
-        add_fluid_source(&mut grid, 20, 20000.0, 65000.0); // Fluid source
-
-        // Update the fluid grid using the new flux calculations
-        let mut new_grid = calculate_fluxes(&grid, &solid_object, GAMMA_AIR);
-        apply_boundary_conditions(&mut new_grid);
-        update_grid(&mut grid, new_grid);
+        let frame_time = last_frame.elapsed().as_secs_f64();
+        last_frame = Instant::now();
+        let step_due = match slow_motion {
+            None => true,
+            Some(seconds) => {
+                // Capped so a long frame doesn't queue up a burst of steps
+                step_budget = (step_budget + frame_time / seconds).min(1.0);
+                step_budget >= 1.0
+            }
+        };
+        if (!paused && step_due) || step_once {
+            step_once = false;
+            step_budget = 0.0;
+            let report = sim.step(dt_override);
+            dt = report.dt;
+            clamps = report.clamps_applied;
+            if !report.is_stable() && !warned_unstable {
+                report.warn_unstable(sim.step_count);
+                warned_unstable = true;
+            }
+            if report.is_supersonic() && !warned_supersonic {
+                report.warn_supersonic(sim.step_count);
+                warned_supersonic = true;
+            }
+            stall.record(sim, &report);
+            logs.record(sim, &report)?;
+            script.run(sim, &mut scenario, &view)?;
+            if let Some(recorder) = recorder.as_deref_mut() {
+                recorder.capture(sim, &view).context("Recording failed")?;
+            }
+            if let Some(steady) = steady.as_deref_mut() {
+                if steady.record(sim, report.dt) && steady.pause {
+                    paused = true;
+                    title_dirty = true;
+                }
+            }
+            if breakpoints.check(sim, &report) {
+                paused = true;
+                title_dirty = true;
+            }
+            if sim.reached_stop() {
+                println!(
+                    "Reached t = {} after {} steps, paused",
+                    sim.sim_time, sim.step_count
+                );
+                // Resuming runs on freely from here
+                sim.stop_at = None;
+                paused = true;
+                title_dirty = true;
+            }
+        }
 
         // Render the grid and airfoil object
-        render_grid(&mut canvas, &grid, &solid_object);
+        render_grid(&mut canvas, &mut texture, sim, &view).context("Failed to draw the grid")?;
+        if let Some((zoom, region, zoom_texture)) = &mut zoomed {
+            draw_zoom(&mut canvas, zoom_texture, sim, &view, zoom, region)
+                .context("Failed to draw the zoomed region")?;
+        }
+
+        // Grab the frame before present, the back buffer is undefined afterwards
+        if take_screenshot {
+            let path = next_free_path("screenshot", "png");
+            match save_canvas_png(&canvas, &path) {
+                Ok(()) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => eprintln!("Screenshot failed: {}", e),
+            }
+        }
+        // After the screenshot, which shouldn't have it in
+        if brush > 0 {
+            draw_brush(&mut canvas, sim, &view, cursor, brush)
+                .context("Failed to draw the brush")?;
+        }
 
         // Calculate FPS
         let now = Instant::now();
-        let frame_time = now.duration_since(last_frame_time);
-        last_frame_time = now;
-
         frame_count += 1;
         if now.duration_since(last_fps_update) >= Duration::from_secs(1) {
             fps = frame_count as f64 / now.duration_since(last_fps_update).as_secs_f64();
             println!("FPS: {:.2}", fps);
+            if sim.settings.conduction > 0.0 {
+                sim.report_solid_temperature();
+            }
             frame_count = 0;
             last_fps_update = now;
+            title_dirty = true;
+        }
+
+        if title_dirty {
+            let mode = if dt_override.is_some() {
+                "manual".to_string()
+            } else if sim.fixed_dt.is_some() {
+                "fixed".to_string()
+            } else if sim.dt_safety < 1.0 {
+                format!("default, safety {:.2}", sim.dt_safety)
+            } else {
+                "default".to_string()
+            };
+            let mut title = format!(
+                "{} | FPS: {:.1} | step {} | t = {:.3}{} | dt: {:.3e} ({}) | view: {}",
+                WINDOW_TITLE,
+                fps,
+                sim.step_count,
+                sim.sim_time,
+                if paused { " (paused)" } else { "" },
+                dt,
+                mode,
+                view.mode.name(),
+            );
+            if sim.settings.wrap_edges {
+                title += " | edges: wrap";
+            }
+            if reversed {
+                title += " | reversed";
+            }
+            if let Some(seconds) = slow_motion {
+                title += &format!(" | slow motion: {:.2} s/step", seconds);
+            }
+            if sim.settings.viscosity > 0.0 {
+                title += &format!(" | viscosity: {:.3}", sim.settings.viscosity);
+            }
+            if editing {
+                title += " | EDIT";
+            }
+            if brush > 0 {
+                title += &format!(" | brush: {}", brush);
+            }
+            if inspecting {
+                title += " | STENCIL";
+            }
+            if zoom_corner.is_some() {
+                title += " | ZOOM: Q on the opposite corner";
+            }
+            if let Some((zoom, _, _)) = &zoomed {
+                title += &format!(
+                    " | zoomed: {}x{} at ({}, {})",
+                    zoom.width, zoom.height, zoom.x, zoom.y
+                );
+            }
+            if let Some(hold) = &sim.max_hold {
+                title += &format!(" | max hold: {}", hold.field.name());
+            }
+            if view.mode == RenderMode::Tracers && !sim.tracers.is_empty() {
+                match view.tracer {
+                    Some(i) => title += &format!(" | tracer {} of {}", i, sim.tracers.len()),
+                    None => title += " | all tracers",
+                }
+            }
+            if let Some(field) = view.histogram {
+                title += &format!(" | histogram: {}", histogram_label(sim, field));
+            }
+            let _ = canvas.window_mut().set_title(&title);
         }
 
         // Present the canvas
@@ -266,4 +3772,375 @@ fn main() {
         // Control frame rate
         std::thread::sleep(Duration::from_millis(1)); // Approx 60 FPS
     }
+
+    script.report();
+    Ok(view)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args().map_err(AppError)?;
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    if options.measure_diffusion {
+        diagnostics::measure_diffusion(options.steps);
+        return Ok(());
+    }
+    if options.measure_sound_speed {
+        diagnostics::measure_sound_speed(&options)?;
+        return Ok(());
+    }
+    if let Some((first, second)) = &options.diff_states {
+        snapshot::diff_csv(first, second)?;
+        return Ok(());
+    }
+
+    let scenario = Scenario::from_options(&options)?;
+    let mut sim = scenario.build()?;
+    sim.stop_at = options.stop_at;
+    sim.dt_safety = options.dt_safety;
+    sim.fixed_dt = options.fixed_dt;
+    sim.sampling = options.sampling;
+    sim.report_obstacle_geometry();
+    if let Some(path) = &options.init_csv {
+        snapshot::load_csv(&mut sim.grid, path)?;
+    }
+    if let Some(dt) = sim.fixed_dt {
+        if sim.settings.local_dt {
+            return Err(
+                AppError("--fixed-dt can't be used with local timestepping".to_string()).into(),
+            );
+        }
+        if let Some(limit) = sim.cfl_dt().filter(|&limit| dt > limit) {
+            eprintln!(
+                "Warning: the fixed dt {:.3e} is over the CFL limit {:.3e} of the initial state",
+                dt, limit
+            );
+        }
+    }
+    if let Some(tracer) = options
+        .show_tracer
+        .flatten()
+        .filter(|&t| t >= sim.tracers.len())
+    {
+        return Err(AppError(format!(
+            "--show-tracer {} but the scenario has {} tracers, counting from 0",
+            tracer,
+            sim.tracers.len()
+        ))
+        .into());
+    }
+    if let Some(&x) = options
+        .flow_meter
+        .iter()
+        .find(|&&x| x == 0 || x >= sim.grid.width())
+    {
+        return Err(AppError(format!(
+            "--flow-meter {} is off the grid, columns go from 1 to {}",
+            x,
+            sim.grid.width() - 1
+        ))
+        .into());
+    }
+    if !options.flow_meter.is_empty() {
+        sim.flow_meter = Some(FlowMeter::new(&options.flow_meter));
+    }
+    if options.mass_balance {
+        if let Some(problem) = MassBalance::problem(&sim.settings) {
+            return Err(AppError(format!("--mass-balance: {}", problem)).into());
+        }
+        sim.mass_balance = Some(MassBalance::new());
+    }
+    sim.allocations = AllocationCheck::new(options.max_step_allocations);
+    if options.reference {
+        let gases = if sim.material.is_some() { 2 } else { 1 };
+        if let Some(problem) = reference::problem(&sim.settings, gases) {
+            return Err(AppError(format!("--reference: {}", problem)).into());
+        }
+        sim.reference = Some(ReferenceCheck::default());
+    }
+    let zoom = options.zoom.map(Zoom::from_list);
+    if let Some(problem) = zoom.and_then(|zoom| zoom.problem(sim.grid.width(), sim.grid.height())) {
+        return Err(AppError(format!("--zoom: {}", problem)).into());
+    }
+    // Started after the initial state is in, so the hold includes it
+    sim.max_hold = options.max_hold.map(|field| MaxHold::new(field, &sim));
+
+    let mut recorder = if options.record_dir.is_some() || options.gif.is_some() {
+        let gif = options.gif.clone().map(|path| (path, options.gif_delay));
+        Some(
+            Recorder::new(
+                options.record_dir.clone(),
+                gif,
+                options.record_every,
+                options.max_frames,
+                options.append,
+            )
+            .map_err(AppError)?,
+        )
+    } else {
+        None
+    };
+    let mut logs = Logs::default();
+    if let Some(path) = &options.log {
+        logs.steps = Some(StepLog::create(path, options.append)?);
+    }
+    if let Some(path) = &options.json_log {
+        logs.json = Some(JsonLog::create(path, options.append)?);
+    }
+    if let Some(path) = &options.force_log {
+        logs.forces = Some(ForceLog::create(path, options.append)?);
+    }
+    if let Some(path) = &options.thermal_log {
+        logs.thermal = Some(ThermalLog::create(path, options.append)?);
+    }
+    let mut steady = options
+        .steady
+        .map(|threshold| SteadyState::new(threshold, options.pause_when_steady));
+    let view = scenario.view.apply(View {
+        color_max: options.color_max.unwrap_or(DEFAULT_COLOR_MAX),
+        cell_borders: options.cell_borders,
+        legend: options.legend,
+        surface_cp: options.surface_cp,
+        obstacle_outline: options.obstacle_outline,
+        arrows: options.arrows,
+        arrow_stride: options.arrow_stride.unwrap_or(DEFAULT_ARROW_STRIDE),
+        cell_size: options.cell_size.unwrap_or(CELL_SIZE),
+        mirror: scenario.symmetry,
+        // Two gases start out showing which is where, unless the scenario says otherwise
+        mode: if sim.material.is_some() {
+            RenderMode::Tracers
+        } else {
+            RenderMode::Density
+        },
+        tracer: options.show_tracer.flatten(),
+        ..View::default()
+    });
+    // What was asked for on the command line beats the scenario's choice
+    let view = View {
+        mode: if options.max_hold.is_some() {
+            RenderMode::MaxHold
+        } else if options.show_tracer.is_some() {
+            RenderMode::Tracers
+        } else {
+            view.mode
+        },
+        ..view
+    };
+    let start_totals = diagnostics::fluid_totals(&sim);
+    let view = if options.headless {
+        let view = View {
+            solid_temperature: view.solid_temperature || sim.settings.conduction > 0.0,
+            ..view
+        };
+        run_headless(
+            &options,
+            &mut sim,
+            &scenario,
+            &view,
+            recorder.as_mut(),
+            &mut logs,
+            steady.as_mut(),
+        )?;
+        view
+    } else {
+        run_window(
+            &mut sim,
+            &scenario,
+            view,
+            recorder.as_mut(),
+            &mut logs,
+            steady.as_mut(),
+            &options.break_on,
+        )?
+    };
+    logs.finish()?;
+    if let Some(steady) = &steady {
+        steady.report();
+    }
+    if let Some(meter) = &sim.flow_meter {
+        meter.report();
+    }
+    if let Some(balance) = &sim.mass_balance {
+        balance.report();
+    }
+    if let Some(recorder) = recorder.take() {
+        recorder.report();
+    }
+
+    if let Some(check) = &sim.reference {
+        check.report()?;
+    }
+    if let Some(check) = &sim.allocations {
+        check.report()?;
+    }
+    match scenario.self_test {
+        Some(SelfTest::Symmetry) => diagnostics::check_symmetry(&sim)?,
+        Some(SelfTest::Sod) => match &scenario.discontinuity {
+            Some(discontinuity) => diagnostics::compare_riemann(&sim, discontinuity)?,
+            None => return Err(AppError("the sod report needs a discontinuity".to_string()).into()),
+        },
+        Some(SelfTest::ShockSpeed) => match &scenario.discontinuity {
+            Some(discontinuity) => diagnostics::check_shock_speed(&sim, discontinuity)?,
+            None => {
+                return Err(
+                    AppError("the shock speed test needs a discontinuity".to_string()).into(),
+                )
+            }
+        },
+        Some(SelfTest::Rest) => diagnostics::check_rest(&sim)?,
+        Some(SelfTest::Physical) => diagnostics::check_physical(&sim)?,
+        Some(SelfTest::Conservation) => diagnostics::check_conservation(&sim, &start_totals)?,
+        Some(SelfTest::ShockAngle) => match scenario.wedge() {
+            Some((leading_edge, tip)) => diagnostics::report_shock_angle(&sim, leading_edge, tip)?,
+            None => return Err(AppError("the shock angle report needs a wedge".to_string()).into()),
+        },
+        Some(SelfTest::Strouhal) => diagnostics::report_strouhal(&sim),
+        Some(SelfTest::Threads) => diagnostics::check_threads(&sim, &scenario)?,
+        Some(SelfTest::Vortex) => match &scenario.vortex {
+            Some(vortex) => diagnostics::compare_vortex(&sim, vortex)?,
+            None => return Err(AppError("the vortex report needs a vortex".to_string()).into()),
+        },
+        None => {}
+    }
+
+    // Write the final frame if asked to
+    if let Some(path) = &options.screenshot {
+        save_grid_png(&sim, &view, path).context("Screenshot failed")?;
+        println!("Saved screenshot to {}", path.display());
+    }
+    if let Some(path) = &options.cp_csv {
+        diagnostics::save_surface_cp(&sim, path)?;
+    }
+    if let Some(path) = &options.dump_csv {
+        snapshot::save_csv(&sim.grid, path)?;
+        println!("Dumped grid to {}", path.display());
+    }
+    if let Some(zoom) = zoom {
+        let region = zoom.run(&sim);
+        zoom.report(&sim, &region);
+        if let Some(path) = &options.zoom_screenshot {
+            let view = View {
+                mirror: false,
+                ..view
+            };
+            save_grid_png(&region, &view, path).context("Zoom screenshot failed")?;
+            println!("Saved the zoomed region to {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::SUPERSAMPLE;
+
+    // A preset stepped the way the headless run steps it
+    fn run(name: &str, steps: u64) -> (Scenario, Simulation) {
+        let scenario = Scenario::preset(name).unwrap();
+        let mut sim = scenario.build().unwrap();
+        while sim.step_count < steps {
+            sim.step(None);
+        }
+        (scenario, sim)
+    }
+
+    #[test]
+    fn shock_tube_stays_near_the_exact_solution() {
+        // Per cell density, velocity and pressure errors, about twice what the legacy
+        // scheme's smearing leaves after 1000 steps
+        let limits = [0.03, 0.15, 0.02];
+        let (scenario, sim) = run("sod", 1000);
+        let (l1, _) = diagnostics::riemann_errors(&sim, &scenario.discontinuity.unwrap());
+        for (error, limit) in l1.iter().zip(limits) {
+            assert!(*error < limit, "L1 {:?} against {:?}", l1, limits);
+        }
+    }
+
+    #[test]
+    fn threads_give_the_same_bits_as_one() {
+        let (scenario, sim) = run("threads", 200);
+        diagnostics::check_threads(&sim, &scenario).unwrap();
+    }
+
+    #[test]
+    fn obstacles_on_the_edges_keep_their_blast_in() {
+        let (_, sim) = run("edge-rest", 1000);
+        diagnostics::check_rest(&sim).unwrap();
+    }
+
+    #[test]
+    fn air_beside_a_solid_stays_at_rest() {
+        let (_, sim) = run("wall-rest", 1000);
+        diagnostics::check_rest(&sim).unwrap();
+    }
+
+    #[test]
+    fn closed_boxes_conserve_mass_and_energy() {
+        // Walled by obstacle cells, and by the boundary pass at the top and bottom
+        for name in ["closed-box", "walled-box"] {
+            let mut sim = Scenario::preset(name).unwrap().build().unwrap();
+            let start = diagnostics::fluid_totals(&sim);
+            while sim.step_count < 100 {
+                sim.step(None);
+            }
+            diagnostics::check_conservation(&sim, &start).unwrap();
+        }
+    }
+
+    #[test]
+    fn smallest_grid_runs_and_stays_a_gas() {
+        let (_, sim) = run("minimal-grid", 1000);
+        assert_eq!((sim.grid.width(), sim.grid.height()), (3, 3));
+        diagnostics::check_physical(&sim).unwrap();
+    }
+
+    // The airfoil as the obstacle menu lays it out on the default grid
+    fn airfoil_coverage(shape: Shape) -> Vec<Vec<Real>> {
+        shape.scaled(80, 60).coverage(80, 60)
+    }
+
+    #[test]
+    fn airfoil_covers_whole_cells() {
+        let coverage = airfoil_coverage(create_airfoil());
+        let solid = coverage.iter().flatten().filter(|&&c| c == 1.0).count();
+        assert!(solid > 0, "the airfoil doesn't cover a single cell");
+    }
+
+    #[test]
+    fn symmetric_airfoil_mirrors_about_its_chord() {
+        // A NACA 0012 level in the middle of the grid, so row y and row 59 - y sample
+        // mirrored points
+        let coverage = airfoil_coverage(create_naca_airfoil(0.0, 0.0, 0.12, 0.0));
+        assert!(coverage.iter().flatten().any(|&c| c > 0.0));
+        for y in 0..coverage.len() {
+            assert_eq!(coverage[y], coverage[coverage.len() - 1 - y], "row {}", y);
+        }
+    }
+
+    #[test]
+    fn pitched_airfoil_stays_on_the_grid() {
+        for angle in [-15.0, 4.0, 15.0] {
+            let shape = create_naca_airfoil(0.02, 0.4, 0.12, angle).scaled(80, 60);
+            // Sample a margin a grid wide all round at the coverage's resolution
+            let step = 1.0 / SUPERSAMPLE as Real;
+            for j in -(60 * SUPERSAMPLE as i64)..(120 * SUPERSAMPLE as i64) {
+                for i in -(80 * SUPERSAMPLE as i64)..(160 * SUPERSAMPLE as i64) {
+                    let (px, py) = ((i as Real + 0.5) * step, (j as Real + 0.5) * step);
+                    let on_grid = (0.0..80.0).contains(&px) && (0.0..60.0).contains(&py);
+                    assert!(
+                        on_grid || !shape.contains(px, py),
+                        "({}, {}) at {} degrees",
+                        px,
+                        py,
+                        angle
+                    );
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,54 @@
+use crate::error::{AppError, Context};
+use crate::Real;
+use std::path::Path;
+
+// Pixels darker than this, on a scale of 0 to 1, are solid
+const SOLID_LUMINANCE: Real = 0.5;
+
+// Solid cells from a black on white image, one nearest pixel sampled per cell. The image
+// is fitted into the grid keeping its aspect ratio and centered like the SVG outlines,
+// the cells it doesn't reach left fluid. Transparent pixels count as fluid whatever
+// their color.
+pub fn load_solid_from_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+) -> Result<Vec<Vec<bool>>, AppError> {
+    let image = image::open(path)
+        .context(&format!("Could not read obstacle mask {}", path.display()))?
+        .to_luma_alpha8();
+    let (image_width, image_height) = image.dimensions();
+    if image_width == 0 || image_height == 0 {
+        return Err(AppError(format!(
+            "obstacle mask {} is empty",
+            path.display()
+        )));
+    }
+
+    // Pixels per cell, and where the image's corner lands
+    let scale = (image_width as Real / width as Real).max(image_height as Real / height as Real);
+    let offset_x = (width as Real - image_width as Real / scale) / 2.0;
+    let offset_y = (height as Real - image_height as Real / scale) / 2.0;
+    let pixel = |cell: usize, offset: Real, size: u32| {
+        let p = ((cell as Real + 0.5 - offset) * scale).floor();
+        (p >= 0.0 && p < size as Real).then_some(p as u32)
+    };
+    Ok((0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    match (
+                        pixel(x, offset_x, image_width),
+                        pixel(y, offset_y, image_height),
+                    ) {
+                        (Some(px), Some(py)) => {
+                            let [luma, alpha] = image.get_pixel(px, py).0;
+                            alpha >= 128 && (luma as Real / 255.0) < SOLID_LUMINANCE
+                        }
+                        _ => false,
+                    }
+                })
+                .collect()
+        })
+        .collect())
+}
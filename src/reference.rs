@@ -0,0 +1,239 @@
+use crate::error::AppError;
+use crate::grid::Grid;
+use crate::{Cell, Real, Scheme, Settings, WallKind};
+
+// How far a reference step may be from the solver's, relative to the size of the cell's
+// state. Both are the same first-order scheme, so only the rounding differs: a handful of
+// ulps, scaled here with the precision Real has.
+const REFERENCE_TOLERANCE: Real = 1000.0 * Real::EPSILON;
+
+#[derive(Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+}
+
+// Why the reference can't follow these settings, if it can't. It only knows the
+// conservative scheme for one gas, with every cell taking the same dt and slip walls.
+pub fn problem(settings: &Settings, gases: usize) -> Option<String> {
+    if settings.scheme != Scheme::FiniteVolume {
+        return Some("there's only a reference for the finite volume scheme".to_string());
+    }
+    if settings.local_dt {
+        return Some("the reference doesn't do local timestepping".to_string());
+    }
+    if settings.wall == WallKind::NoSlip {
+        return Some("the reference only has slip walls".to_string());
+    }
+    if gases > 1 {
+        return Some("the reference only knows one gas".to_string());
+    }
+    None
+}
+
+// The conservative scheme again, written out the plain way to check finite_volume against:
+// one pass over all the faces, every face's Rusanov flux worked out from the primitives
+// once and taken out of the cell on one side and put into the other. Slow, single-threaded
+// and allocating, on purpose; keep it that way. Everything else a step does (sources,
+// drag, the sponge and so on) happens outside, the same for both.
+pub fn advance(
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    solid_fraction: &[Vec<Real>],
+    settings: &Settings,
+    dt: Real,
+) -> Vec<Vec<Cell>> {
+    let (width, height) = (grid.width(), grid.height());
+    let wrap = settings.periodic || settings.wrap_edges;
+    let gamma = settings.gamma;
+    let mut change = vec![vec![Cell::zero(); width]; height];
+
+    // The face before cell `i` along an axis of `n` cells, and the one after the last,
+    // which is the first one again on a torus
+    let faces = |n: usize| {
+        let last = if wrap { n } else { n + 1 };
+        (0..last).map(move |i| {
+            let before = if i == 0 {
+                wrap.then(|| n - 1)
+            } else {
+                Some(i - 1)
+            };
+            (before, (i < n).then_some(i))
+        })
+    };
+    let mut exchange = |before: Option<(usize, usize)>, after: Option<(usize, usize)>, axis| {
+        let fluid = |c: Option<(usize, usize)>| c.filter(|&(x, y)| !solid[y][x]);
+        // A fluid cell's view of the far side when there's no fluid there: the same gas
+        // past the left edge and open top and bottom ones, the outlet's past the right, its
+        // mirror image in a wall
+        let ghost = |cell: Cell, other: Option<(usize, usize)>, right: bool| match (other, axis) {
+            (None, Axis::X) if right => settings.outlet.outside(&cell, gamma),
+            (None, Axis::X) => cell,
+            (None, Axis::Y) if settings.open_edges => cell,
+            (_, Axis::X) => Cell {
+                momentum_x: -cell.momentum_x,
+                ..cell
+            },
+            (_, Axis::Y) => Cell {
+                momentum_y: -cell.momentum_y,
+                ..cell
+            },
+        };
+        match (fluid(before), fluid(after)) {
+            (Some((bx, by)), Some((ax, ay))) => {
+                let flux = rusanov(grid[by][bx], grid[ay][ax], axis, gamma);
+                change[by][bx] = change[by][bx] - flux;
+                change[ay][ax] = change[ay][ax] + flux;
+            }
+            (Some((bx, by)), None) => {
+                let cell = grid[by][bx];
+                let flux = rusanov(cell, ghost(cell, after, true), axis, gamma);
+                change[by][bx] = change[by][bx] - flux;
+            }
+            (None, Some((ax, ay))) => {
+                let cell = grid[ay][ax];
+                let flux = rusanov(ghost(cell, before, false), cell, axis, gamma);
+                change[ay][ax] = change[ay][ax] + flux;
+            }
+            (None, None) => {}
+        }
+    };
+    for y in 0..height {
+        for (before, after) in faces(width) {
+            exchange(before.map(|x| (x, y)), after.map(|x| (x, y)), Axis::X);
+        }
+    }
+    for x in 0..width {
+        for (before, after) in faces(height) {
+            exchange(before.map(|y| (x, y)), after.map(|y| (x, y)), Axis::Y);
+        }
+    }
+
+    let mut new_grid = grid.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            if solid[y][x] {
+                continue;
+            }
+            let cell = grid[y][x];
+            let mut change = change[y][x];
+            change.momentum_y += cell.density * settings.gravity;
+            change.energy += cell.momentum_y * settings.gravity;
+            new_grid[y][x] = cell + change * (dt * (1.0 - solid_fraction[y][x]));
+        }
+    }
+    new_grid
+}
+
+// What crosses the face from `before` to `after` per unit time: the average of the two
+// sides' Euler fluxes, less the jump between them times the fastest wave either sends
+fn rusanov(before: Cell, after: Cell, axis: Axis, gamma: Real) -> Cell {
+    let (flux_before, speed_before) = euler(before, axis, gamma);
+    let (flux_after, speed_after) = euler(after, axis, gamma);
+    let speed = speed_before.max(speed_after);
+    (flux_before + flux_after) * 0.5 - (after - before) * (0.5 * speed)
+}
+
+// The Euler flux of one state through a face normal to `axis`, and the fastest signal it
+// sends through it
+fn euler(cell: Cell, axis: Axis, gamma: Real) -> (Cell, Real) {
+    let u = cell.momentum_x / cell.density;
+    let v = cell.momentum_y / cell.density;
+    let pressure = (gamma - 1.0) * (cell.energy - 0.5 * cell.density * (u * u + v * v));
+    let sound = (gamma * pressure / cell.density).sqrt();
+    let (normal, push_x, push_y) = match axis {
+        Axis::X => (u, pressure, 0.0),
+        Axis::Y => (v, 0.0, pressure),
+    };
+    let flux = Cell {
+        density: cell.density * normal,
+        momentum_x: cell.momentum_x * normal + push_x,
+        momentum_y: cell.momentum_y * normal + push_y,
+        energy: (cell.energy + pressure) * normal,
+    };
+    (flux, normal.abs() + sound)
+}
+
+// The largest difference seen between the solver's steps and the reference's, up to the
+// first step that left the gas unphysical somewhere. Past that both solvers carry on with
+// garbage and round it differently, so there's nothing to compare.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReferenceCheck {
+    worst: Real,
+    step: u64,
+    at: (usize, usize),
+    stopped: Option<u64>,
+}
+
+impl ReferenceCheck {
+    pub fn active(&self) -> bool {
+        self.stopped.is_none()
+    }
+
+    // Every fluid cell's largest difference, over the largest of its components
+    pub fn record(
+        &mut self,
+        expected: &[Vec<Cell>],
+        got: &[Vec<Cell>],
+        solid: &[Vec<bool>],
+        step: u64,
+    ) {
+        for (y, (expected_row, got_row)) in expected.iter().zip(got).enumerate() {
+            for (x, (e, g)) in expected_row.iter().zip(got_row).enumerate() {
+                if solid[y][x] {
+                    continue;
+                }
+                if !(e.density > 0.0 && e.energy.is_finite()) {
+                    self.stopped.get_or_insert(step);
+                    continue;
+                }
+                let parts = |c: &Cell| [c.density, c.momentum_x, c.momentum_y, c.energy];
+                let scale = parts(e)
+                    .iter()
+                    .fold(Real::MIN_POSITIVE, |m, v| m.max(v.abs()));
+                let difference = parts(e)
+                    .iter()
+                    .zip(parts(g))
+                    .map(|(e, g)| (e - g).abs() / scale)
+                    .fold(0.0, |worst: Real, d| {
+                        if d.is_nan() {
+                            Real::INFINITY
+                        } else {
+                            worst.max(d)
+                        }
+                    });
+                if difference > self.worst {
+                    self.worst = difference;
+                    self.step = step;
+                    self.at = (x, y);
+                }
+            }
+        }
+    }
+
+    pub fn report(&self) -> Result<(), AppError> {
+        let ok = self.worst <= REFERENCE_TOLERANCE;
+        println!(
+            "Reference: largest relative difference from the solver {:.3e}, at step {} in ({}, {}) {}",
+            self.worst,
+            self.step,
+            self.at.0,
+            self.at.1,
+            if ok { "ok" } else { "FAILED" }
+        );
+        if let Some(step) = self.stopped {
+            println!(
+                "Reference: stopped comparing at step {}, where the gas went unphysical",
+                step
+            );
+        }
+        if ok {
+            Ok(())
+        } else {
+            Err(AppError(format!(
+                "the solver's step {} disagrees with the reference at ({}, {})",
+                self.step, self.at.0, self.at.1
+            )))
+        }
+    }
+}
@@ -0,0 +1,1434 @@
+use crate::consts::PI;
+use crate::diagnostics::{surface_cp, MaxHold};
+use crate::font::{draw_text, ADVANCE, LINE_HEIGHT};
+use crate::grid::Grid;
+use crate::zoom::Zoom;
+use crate::{
+    calculate_pressure, compute_density_gradient, compute_divergence, compute_vorticity,
+    kinetic_energy, temperature, Cell, Real, Region, Simulation, Units, CELL_LENGTH, CELL_SIZE,
+};
+use rayon::prelude::*;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture};
+use sdl2::surface::Surface;
+use sdl2::video::Window;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RenderMode {
+    Density,
+    // Fixed mapping around the ambient density, up to View::color_max
+    Anchored,
+    // The density less the ambient's, red thinner and blue denser, scaled to the largest
+    // difference so a faint wave shows as well as a strong one
+    Fluctuation,
+    Divergence,
+    KineticEnergy,
+    InternalEnergy,
+    // Brightness from the density as in Anchored, hue from the vorticity
+    DensityVorticity,
+    // The running max hold, lowest to highest held value. Plain density while none runs.
+    MaxHold,
+    // The tracers in their colors, added up over black. Plain density without any.
+    Tracers,
+    // What the artificial viscosity turned into heat in the last step, black without it
+    Dissipation,
+    // Every cell flat in the color of its role, see Region
+    Regions,
+    // Numerical schlieren, darker the steeper the density changes
+    Schlieren,
+}
+
+impl RenderMode {
+    pub fn next(self) -> RenderMode {
+        match self {
+            RenderMode::Density => RenderMode::Anchored,
+            RenderMode::Anchored => RenderMode::Fluctuation,
+            RenderMode::Fluctuation => RenderMode::Divergence,
+            RenderMode::Divergence => RenderMode::Schlieren,
+            RenderMode::Schlieren => RenderMode::KineticEnergy,
+            RenderMode::KineticEnergy => RenderMode::InternalEnergy,
+            RenderMode::InternalEnergy => RenderMode::DensityVorticity,
+            RenderMode::DensityVorticity => RenderMode::MaxHold,
+            RenderMode::MaxHold => RenderMode::Tracers,
+            RenderMode::Tracers => RenderMode::Dissipation,
+            RenderMode::Dissipation => RenderMode::Regions,
+            RenderMode::Regions => RenderMode::Density,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderMode::Density => "density",
+            RenderMode::Anchored => "anchored density",
+            RenderMode::Fluctuation => "density fluctuation",
+            RenderMode::Divergence => "divergence",
+            RenderMode::Schlieren => "schlieren",
+            RenderMode::KineticEnergy => "kinetic energy",
+            RenderMode::InternalEnergy => "internal energy",
+            RenderMode::DensityVorticity => "density + vorticity",
+            RenderMode::MaxHold => "max hold",
+            RenderMode::Tracers => "tracers",
+            RenderMode::Dissipation => "viscous dissipation",
+            RenderMode::Regions => {
+                "regions (jet green, outflow red, walls yellow/orange, sponge purple)"
+            }
+        }
+    }
+}
+
+// What the histogram overlay bins
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistogramField {
+    Density,
+    Pressure,
+}
+
+impl HistogramField {
+    // Off, density, pressure, off again
+    pub fn cycle(current: Option<HistogramField>) -> Option<HistogramField> {
+        match current {
+            None => Some(HistogramField::Density),
+            Some(HistogramField::Density) => Some(HistogramField::Pressure),
+            Some(HistogramField::Pressure) => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HistogramField::Density => "density",
+            HistogramField::Pressure => "pressure",
+        }
+    }
+}
+
+// Everything about how the field is drawn, as opposed to what is simulated
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub mode: RenderMode,
+    pub solid_temperature: bool, // Color obstacles by their own temperature instead of flat gray
+    pub histogram: Option<HistogramField>, // Window only, drawn over the bottom left corner
+    pub color_max: Real,         // Density at the warm end of the anchored mode
+    pub cell_borders: bool,      // Darken the edge of every cell to show the grid
+    pub mirror: bool,            // Draw the grid and its reflection below, for a symmetry plane
+    pub tracer: Option<usize>,   // The only tracer the tracer mode draws, None for all of them
+    pub stencil: Option<(usize, usize)>, // Window only, the cell the stencil inspector shows
+    pub legend: bool,            // Colorbar and scale bar over the bottom right corner
+    pub surface_cp: bool,        // The obstacle outlined in its pressure coefficient
+    pub obstacle_outline: bool,  // Only the obstacle's surface drawn, the field showing inside
+    pub arrows: bool,            // The flow's direction drawn over the field
+    pub arrow_stride: usize,     // Cells between arrows along each axis
+    pub cell_size: u32,          // Pixels a cell, for the window's starting size and screenshots
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            mode: RenderMode::Density,
+            solid_temperature: false,
+            histogram: None,
+            color_max: DEFAULT_COLOR_MAX,
+            cell_borders: false,
+            mirror: false,
+            tracer: None,
+            stencil: None,
+            legend: false,
+            surface_cp: false,
+            obstacle_outline: false,
+            arrows: false,
+            arrow_stride: DEFAULT_ARROW_STRIDE,
+            cell_size: CELL_SIZE,
+        }
+    }
+}
+
+// GIF frames are drawn smaller than the window, to keep clips small enough to share.
+// Speed trades the palette's quality for encoding time, 1 (best) to 30 (fastest).
+const GIF_CELL_SIZE: u32 = 4;
+const GIF_SPEED: i32 = 10;
+
+const BRUSH_SEGMENTS: usize = 48; // Straight pieces of the brush outline
+
+const STENCIL_ARROW_HEAD: Real = 0.25; // Cells
+const STENCIL_TEXT_PADDING: u32 = 4;
+
+const HISTOGRAM_BINS: usize = 32;
+const HISTOGRAM_BAR_WIDTH: u32 = 6; // Pixels
+const HISTOGRAM_HEIGHT: u32 = 100;
+const HISTOGRAM_MARGIN: u32 = 8;
+const HISTOGRAM_TEXT_PADDING: u32 = 3;
+
+const LEGEND_MARGIN: u32 = 8;
+const LEGEND_PADDING: u32 = 4;
+const COLORBAR_WIDTH: u32 = 160; // Pixels, one color per column
+const COLORBAR_HEIGHT: u32 = 12;
+const SCALE_BAR_HEIGHT: u32 = 4;
+const SCALE_BAR_CELLS: [usize; 4] = [10, 5, 2, 1]; // The longest that fits goes in
+const SURFACE_CP_WIDTH: Real = 0.3; // Of a cell, how thick the outline is drawn
+const OUTLINE_WIDTH: Real = 0.15; // The same for the plain obstacle outline
+const OUTLINE_COLOR: Color = Color::RGB(200, 200, 200);
+pub const DEFAULT_ARROW_STRIDE: usize = 4;
+const ARROW_HEAD: Real = 0.3; // Of the arrow's length
+const ARROW_COLOR: Color = Color::RGB(0, 0, 0);
+const SCHLIEREN_GAIN: Real = 8.0; // How fast the schlieren view darkens along its scale
+
+const SOLID_COLOR: Color = Color::RGB(100, 100, 100); // Solid objects as gray
+const BORDER_SHADE: Real = 0.35; // How far a cell border is darkened toward black
+
+// Where density_color saturates, so both modes top out at the same density by default
+pub const DEFAULT_COLOR_MAX: Real = 10.0;
+const AMBIENT_COLOR: Color = Color::RGB(60, 90, 200);
+const DENSE_COLOR: Color = Color::RGB(255, 220, 80);
+const THIN_COLOR: Color = Color::RGB(0, 20, 60);
+
+fn density_color(cell: &Cell) -> Color {
+    let density_color = (cell.density * 25.5).min(255.0) as u8;
+    Color::RGB(density_color, density_color, 255)
+}
+
+// The ambient density is always the same midtone blue; denser gas warms toward yellow,
+// reached at `max`, and thinner gas fades toward near black at zero
+fn anchored_color(density: Real, ambient: Real, max: Real) -> Color {
+    if !density.is_finite() {
+        return AMBIENT_COLOR;
+    }
+    if density >= ambient {
+        let t = (density - ambient) / (max - ambient).max(Real::MIN_POSITIVE);
+        blend(AMBIENT_COLOR, DENSE_COLOR, t.min(1.0))
+    } else {
+        blend(
+            AMBIENT_COLOR,
+            THIN_COLOR,
+            (1.0 - density / ambient).min(1.0),
+        )
+    }
+}
+
+// Maps value / scale in [-1, 1] onto red (negative) - white - blue (positive)
+fn diverging_color(value: Real, scale: Real) -> Color {
+    let t = if scale > 0.0 && value.is_finite() {
+        (value / scale).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    let fade = (255.0 * (1.0 - t.abs())) as u8;
+    if t < 0.0 {
+        Color::RGB(255, fade, fade)
+    } else {
+        Color::RGB(fade, fade, 255)
+    }
+}
+
+// White for uniform gas, going gray and then black as the density gradient nears `scale`,
+// falling off exponentially so the weak waves still show next to the shocks
+fn schlieren_color(gradient: Real, scale: Real) -> Color {
+    let t = if scale > 0.0 && gradient.is_finite() {
+        gradient / scale
+    } else {
+        0.0
+    };
+    let gray = (255.0 * (-SCHLIEREN_GAIN * t).exp()).round() as u8;
+    Color::RGB(gray, gray, gray)
+}
+
+// Hue in degrees, saturation and value in [0, 1]
+fn hsv_color(hue: Real, saturation: Real, value: Real) -> Color {
+    let chroma = value * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let channel = |c: Real| ((c + value - chroma) * 255.0).round() as u8;
+    Color::RGB(channel(r), channel(g), channel(b))
+}
+
+// Half brightness at the ambient density, full at `max` and black at zero. The vorticity
+// over `scale` saturates the color toward red for clockwise turning and blue for
+// counterclockwise, leaving still gas gray.
+fn density_vorticity_color(
+    density: Real,
+    ambient: Real,
+    max: Real,
+    vorticity: Real,
+    scale: Real,
+) -> Color {
+    let value = if !density.is_finite() {
+        0.5
+    } else if density >= ambient {
+        0.5 + 0.5 * ((density - ambient) / (max - ambient).max(Real::MIN_POSITIVE)).min(1.0)
+    } else {
+        0.5 * density / ambient
+    };
+    let t = if scale > 0.0 && vorticity.is_finite() {
+        (vorticity / scale).clamp(-1.0, 1.0)
+    } else {
+        0.0
+    };
+    let hue = if t > 0.0 { 0.0 } else { 240.0 };
+    hsv_color(hue, t.abs(), value)
+}
+
+// Black - red - yellow - white for t in [0, 1]
+fn heat_color(t: Real) -> Color {
+    let t = if t.is_finite() {
+        t.clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let channel = |start: Real| ((t * 3.0 - start).clamp(0.0, 1.0) * 255.0) as u8;
+    Color::RGB(channel(0.0), channel(1.0), channel(2.0))
+}
+
+// `a` moved a fraction t of the way to `b`
+fn blend(a: Color, b: Color, t: Real) -> Color {
+    let mix = |a: u8, b: u8| (a as Real + (b as Real - a as Real) * t).round() as u8;
+    Color::RGB(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+fn max_abs(field: &[Vec<Real>]) -> Real {
+    field
+        .iter()
+        .flatten()
+        .filter(|v| v.is_finite())
+        .fold(0.0, |max: Real, v| max.max(v.abs()))
+}
+
+// One of the energy densities on every fluid cell, zero inside obstacles so they don't set
+// the color scale
+fn energy_field(sim: &Simulation, energy: fn(&Cell) -> Real) -> Vec<Vec<Real>> {
+    sim.grid
+        .iter()
+        .zip(&sim.solid)
+        .map(|(row, solid_row)| {
+            row.iter()
+                .zip(solid_row)
+                .map(|(cell, &solid)| if solid { 0.0 } else { energy(cell) })
+                .collect()
+        })
+        .collect()
+}
+
+// How far every fluid cell's density is off the ambient one, which is also the free
+// stream's since the physical jet and the sponge both bring in gas at it. Zero inside
+// obstacles.
+fn fluctuation_field(sim: &Simulation) -> Vec<Vec<Real>> {
+    let ambient = sim.settings.ambient().density;
+    sim.grid
+        .iter()
+        .zip(&sim.solid)
+        .map(|(row, solid_row)| {
+            row.iter()
+                .zip(solid_row)
+                .map(|(cell, &solid)| if solid { 0.0 } else { cell.density - ambient })
+                .collect()
+        })
+        .collect()
+}
+
+// The held maxima measured up from the lowest one in the fluid, so the heat scale spans
+// just the range the hold has seen
+fn held_field(hold: &MaxHold, solid: &[Vec<bool>]) -> Vec<Vec<Real>> {
+    let floor = held_floor(hold, solid);
+    hold.values
+        .iter()
+        .zip(solid)
+        .map(|(row, solid_row)| {
+            row.iter()
+                .zip(solid_row)
+                .map(|(&v, &solid)| if solid { 0.0 } else { v - floor })
+                .collect()
+        })
+        .collect()
+}
+
+// The lowest peak held in the gas, where the max hold mode starts
+fn held_floor(hold: &MaxHold, solid: &[Vec<bool>]) -> Real {
+    hold.values
+        .iter()
+        .flatten()
+        .zip(solid.iter().flatten())
+        .filter(|(v, &is_solid)| !is_solid && v.is_finite())
+        .fold(Real::INFINITY, |min, (&v, _)| min.min(v))
+}
+
+fn region_color(region: Region) -> Color {
+    match region {
+        Region::Solid => SOLID_COLOR,
+        Region::Inflow => Color::RGB(60, 200, 80),
+        Region::Outflow => Color::RGB(210, 60, 60),
+        Region::Wall => Color::RGB(230, 210, 60),
+        Region::NoSlip => Color::RGB(230, 130, 40),
+        Region::Sponge => Color::RGB(150, 70, 200),
+        Region::Interior => Color::RGB(20, 30, 70),
+    }
+}
+
+// The shown tracers at (x, y), each one's color scaled by its concentration and summed
+fn tracer_color(sim: &Simulation, shown: Option<usize>, x: usize, y: usize) -> Color {
+    let mut rgb = [0.0; 3];
+    for (_, field) in sim
+        .tracers
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| shown.is_none_or(|shown| shown == i))
+    {
+        let c = field.concentration[y][x];
+        for (channel, &color) in rgb.iter_mut().zip(&field.color) {
+            *channel += c * color as Real;
+        }
+    }
+    let [r, g, b] = rgb.map(|channel| channel.clamp(0.0, 255.0) as u8);
+    Color::RGB(r, g, b)
+}
+
+// Per-cell colors for the given view, shared by the window and the PNG output
+// The field the mode draws other than the density, if it isn't the density, and the value
+// at the top of its color scale
+fn mode_field(sim: &Simulation, view: &View) -> (Option<Vec<Vec<Real>>>, Real) {
+    let (grid, solid) = (&sim.grid, &sim.solid);
+    let field = match view.mode {
+        RenderMode::Density | RenderMode::Anchored | RenderMode::Tracers | RenderMode::Regions => {
+            None
+        }
+        RenderMode::MaxHold => sim.max_hold.as_ref().map(|hold| held_field(hold, solid)),
+        RenderMode::Fluctuation => Some(fluctuation_field(sim)),
+        RenderMode::Divergence => Some(compute_divergence(
+            grid,
+            solid,
+            sim.settings.layout,
+            sim.settings.periodic,
+        )),
+        RenderMode::Schlieren => Some(compute_density_gradient(grid, solid)),
+        RenderMode::KineticEnergy => Some(energy_field(sim, kinetic_energy)),
+        RenderMode::InternalEnergy => Some(energy_field(sim, |c| c.energy - kinetic_energy(c))),
+        RenderMode::Dissipation => Some(sim.dissipation.clone()),
+        RenderMode::DensityVorticity => Some(compute_vorticity(
+            grid,
+            solid,
+            sim.settings.layout,
+            sim.settings.periodic,
+        )),
+    };
+    // Energies are drawn up from zero, so only positive values set their scale. Where the
+    // scheme has pushed the kinetic part past the total the internal energy goes negative,
+    // and those cells draw black.
+    let scale = match (&field, view.mode) {
+        (
+            Some(field),
+            RenderMode::Fluctuation | RenderMode::Divergence | RenderMode::DensityVorticity,
+        ) => max_abs(field),
+        (Some(field), _) => field
+            .iter()
+            .flatten()
+            .filter(|v| v.is_finite())
+            .fold(0.0, |max: Real, &v| max.max(v)),
+        (None, _) => 0.0,
+    };
+    (field, scale)
+}
+
+fn compute_colors(sim: &Simulation, view: &View) -> Vec<Vec<Color>> {
+    let (grid, solid) = (&sim.grid, &sim.solid);
+    let (field, scale) = mode_field(sim, view);
+
+    // Solid temperatures are scaled from ambient up to the hottest obstacle cell
+    let ambient_temperature = temperature(&sim.settings.ambient(), sim.settings.gamma);
+    let ambient_density = sim.settings.ambient().density;
+    let max_temperature = sim
+        .solid_temperature
+        .iter()
+        .flatten()
+        .zip(solid.iter().flatten())
+        .filter(|(_, &is_solid)| is_solid)
+        .fold(ambient_temperature, |max, (&t, _)| max.max(t));
+    let temperature_range = max_temperature - ambient_temperature;
+
+    // Rows are colored in parallel, every cell only reading the fields computed above
+    grid.par_iter()
+        .enumerate()
+        .map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(x, cell)| {
+                    if solid[y][x] && !view.obstacle_outline {
+                        if view.solid_temperature {
+                            let t = sim.solid_temperature[y][x] - ambient_temperature;
+                            return heat_color(t / temperature_range);
+                        }
+                        return SOLID_COLOR;
+                    }
+                    let color = match (&field, view.mode) {
+                        (None, RenderMode::Anchored) => {
+                            anchored_color(cell.density, ambient_density, view.color_max)
+                        }
+                        (None, RenderMode::Tracers) if !sim.tracers.is_empty() => {
+                            tracer_color(sim, view.tracer, x, y)
+                        }
+                        (None, RenderMode::Regions) => region_color(sim.region(x, y)),
+                        (None, _) => density_color(cell),
+                        // Compression (negative divergence) is red, expansion blue. Thinner
+                        // than ambient is red too, denser blue.
+                        (Some(field), RenderMode::Fluctuation | RenderMode::Divergence) => {
+                            diverging_color(field[y][x], scale)
+                        }
+                        (Some(field), RenderMode::Schlieren) => schlieren_color(field[y][x], scale),
+                        (Some(field), RenderMode::DensityVorticity) => density_vorticity_color(
+                            cell.density,
+                            ambient_density,
+                            view.color_max,
+                            field[y][x],
+                            scale,
+                        ),
+                        (Some(field), _) => heat_color(field[y][x] / scale),
+                    };
+                    if view.obstacle_outline {
+                        return color;
+                    }
+                    blend(color, SOLID_COLOR, sim.solid_fraction[y][x])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// The largest rectangle with the grid's aspect ratio that fits the window, centered. A window
+// resized to another shape gets black bars instead of stretched cells.
+fn letterbox(window: (u32, u32), grid: (u32, u32)) -> Rect {
+    let scale = (window.0 as Real / grid.0 as Real).min(window.1 as Real / grid.1 as Real);
+    let width = ((grid.0 as Real * scale).round() as u32).max(1);
+    let height = ((grid.1 as Real * scale).round() as u32).max(1);
+    Rect::new(
+        ((window.0 - width.min(window.0)) / 2) as i32,
+        ((window.1 - height.min(window.1)) / 2) as i32,
+        width,
+        height,
+    )
+}
+
+// The grid position, in cells, under a pixel of the window, None over the black bars. With
+// the mirror drawn, both images map onto the simulated half.
+pub fn window_to_cell(
+    window: (u32, u32),
+    sim: &Simulation,
+    view: &View,
+    pixel: (i32, i32),
+) -> Option<(Real, Real)> {
+    let (width, height) = (sim.grid[0].len() as Real, sim.grid.len() as Real);
+    let rows = if view.mirror { 2.0 * height } else { height };
+    let target = letterbox(window, (width as u32, rows as u32));
+    let x = (pixel.0 - target.x()) as Real / target.width() as Real * width;
+    let y = (pixel.1 - target.y()) as Real / target.height() as Real * rows;
+    if !(0.0..width).contains(&x) || !(0.0..rows).contains(&y) {
+        return None;
+    }
+    Some((x, if y >= height { rows - y } else { y }))
+}
+
+// The cell under a pixel of the window, see window_to_cell
+pub fn cell_under(
+    window: (u32, u32),
+    sim: &Simulation,
+    view: &View,
+    pixel: (i32, i32),
+) -> Option<(usize, usize)> {
+    let (x, y) = window_to_cell(window, sim, view, pixel)?;
+    // The mirror's far edge lands exactly on the grid height
+    Some((x as usize, (y as usize).min(sim.grid.len() - 1)))
+}
+
+// The outline of the brush around the cell under `pixel`, a circle through the middle of
+// the ring of cells just outside its reach. Nothing off the grid.
+pub fn draw_brush(
+    canvas: &mut Canvas<Window>,
+    sim: &Simulation,
+    view: &View,
+    pixel: (i32, i32),
+    radius: usize,
+) -> Result<(), String> {
+    let window = canvas.output_size()?;
+    if window_to_cell(window, sim, view, pixel).is_none() {
+        return Ok(());
+    }
+    let (width, height) = (sim.grid[0].len() as u32, sim.grid.len() as u32);
+    let rows = if view.mirror { 2 * height } else { height };
+    let target = letterbox(window, (width, rows));
+    let scale = target.width() as Real / width as Real;
+    // The cell's middle where it's drawn, which on the mirrored half is the reflection
+    let snap = |offset: i32, start: i32| ((offset - start) as Real / scale).floor() + 0.5;
+    let center_x = target.x() as Real + snap(pixel.0, target.x()) * scale;
+    let center_y = target.y() as Real + snap(pixel.1, target.y()) * scale;
+    let r = (radius as Real + 0.5) * scale;
+    let points: Vec<Point> = (0..=BRUSH_SEGMENTS)
+        .map(|i| {
+            let angle = 2.0 * PI * i as Real / BRUSH_SEGMENTS as Real;
+            Point::new(
+                (center_x + r * angle.cos()).round() as i32,
+                (center_y + r * angle.sin()).round() as i32,
+            )
+        })
+        .collect();
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.draw_lines(points.as_slice())
+}
+
+// Draws one pixel per cell into a streaming texture and lets the renderer scale it up to the
+// window, instead of a fill_rect per cell. That's one upload and one copy a frame however
+// many cells changed, so there are no per-cell draw calls left that redrawing only the
+// changed cells would save.
+pub fn render_grid(
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    sim: &Simulation,
+    view: &View,
+) -> Result<(), String> {
+    let (width, height, pixels) = render_grid_to_buffer(sim, view, 1);
+    texture
+        .update(None, &pixels, width as usize * 3)
+        .map_err(|e| e.to_string())?;
+    let target = letterbox(canvas.output_size()?, (width, height));
+    canvas.copy(texture, None, target)?;
+    if view.cell_borders {
+        draw_cell_borders(canvas, target, width, height)?;
+    }
+    if let Some(field) = view.histogram {
+        draw_histogram(canvas, sim, field)?;
+    }
+    if let Some(cell) = view.stencil {
+        draw_stencil(canvas, sim, target, width, cell)?;
+    }
+    if view.obstacle_outline {
+        draw_obstacle_outline(canvas, sim, target)?;
+    }
+    if view.arrows {
+        draw_arrows(canvas, sim, target, view.arrow_stride)?;
+    }
+    if view.surface_cp {
+        draw_surface_cp(canvas, sim, target)?;
+    }
+    if view.legend {
+        draw_legend(canvas, sim, view, target)?;
+    }
+    Ok(())
+}
+
+// A zoomed region's fine cells drawn over the coarse ones it was made from, outlined. On
+// the simulated half when mirrored.
+pub fn draw_zoom(
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+    sim: &Simulation,
+    view: &View,
+    zoom: &Zoom,
+    region: &Simulation,
+) -> Result<(), String> {
+    let inset_view = View {
+        mirror: false,
+        cell_borders: false,
+        histogram: None,
+        stencil: None,
+        legend: false,
+        surface_cp: false,
+        ..*view
+    };
+    let (width, _, pixels) = render_grid_to_buffer(region, &inset_view, 1);
+    texture
+        .update(None, &pixels, width as usize * 3)
+        .map_err(|e| e.to_string())?;
+    let columns = sim.grid[0].len() as u32;
+    let rows = sim.grid.len() as u32 * if view.mirror { 2 } else { 1 };
+    let target = letterbox(canvas.output_size()?, (columns, rows));
+    let scale = target.width() as Real / columns as Real;
+    let at = |cells: usize| (cells as Real * scale).round() as i32;
+    let rect = Rect::new(
+        target.x() + at(zoom.x),
+        target.y() + at(zoom.y),
+        at(zoom.width).max(1) as u32,
+        at(zoom.height).max(1) as u32,
+    );
+    canvas.copy(texture, None, rect)?;
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.draw_rect(rect)
+}
+
+// The inspected cell outlined, with an arrow in from each neighbor its update reads, green
+// where mass comes in through that side and red where it goes out, and a bar where the side
+// is a wall or an edge. The neighbors' density and pressure go in a panel in the top left.
+// Drawn on the simulated half when mirrored.
+fn draw_stencil(
+    canvas: &mut Canvas<Window>,
+    sim: &Simulation,
+    target: Rect,
+    columns: u32,
+    (x, y): (usize, usize),
+) -> Result<(), String> {
+    let scale = target.width() as Real / columns as Real;
+    let middle = |offset: (Real, Real)| {
+        (
+            target.x() as Real + (x as Real + 0.5 + offset.0) * scale,
+            target.y() as Real + (y as Real + 0.5 + offset.1) * scale,
+        )
+    };
+    let point = |(px, py): (Real, Real)| Point::new(px.round() as i32, py.round() as i32);
+    canvas.set_draw_color(Color::RGB(255, 255, 0));
+    let (left, top) = middle((-0.5, -0.5));
+    canvas.draw_rect(Rect::new(
+        left.round() as i32,
+        top.round() as i32,
+        scale.round().max(1.0) as u32,
+        scale.round().max(1.0) as u32,
+    ))?;
+    let Some(faces) = sim.stencil(x, y) else {
+        return Ok(());
+    };
+
+    let sides = [(-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0)];
+    let mut lines = Vec::new();
+    for ((dx, dy), (face, label)) in sides.iter().zip(faces.iter().zip(["L", "R", "U", "D"])) {
+        let edge = middle((dx / 2.0, dy / 2.0));
+        match face.neighbor {
+            Some((nx, ny)) => {
+                let color = if face.flux.density >= 0.0 {
+                    Color::RGB(80, 255, 80)
+                } else {
+                    Color::RGB(255, 80, 80)
+                };
+                canvas.set_draw_color(color);
+                canvas.draw_line(point(middle((*dx, *dy))), point(edge))?;
+                // Two short strokes back from the tip
+                let head = scale * STENCIL_ARROW_HEAD;
+                for side in [-1.0, 1.0] {
+                    let back = (
+                        edge.0 + dx * head - dy * side * head,
+                        edge.1 + dy * head + dx * side * head,
+                    );
+                    canvas.draw_line(point(edge), point(back))?;
+                }
+                lines.push(format!(
+                    "{} ({},{}) rho {:.3e} p {:.3e}",
+                    label,
+                    nx,
+                    ny,
+                    face.across.density,
+                    calculate_pressure(&face.across, sim.gamma_at(nx, ny))
+                ));
+            }
+            None => {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                let half = scale / 2.0;
+                let (from, to) = if *dx != 0.0 {
+                    ((edge.0, edge.1 - half), (edge.0, edge.1 + half))
+                } else {
+                    ((edge.0 - half, edge.1), (edge.0 + half, edge.1))
+                };
+                canvas.draw_line(point(from), point(to))?;
+                lines.push(format!("{} boundary", label));
+            }
+        }
+    }
+
+    let panel_width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as u32 * ADVANCE
+        + 2 * STENCIL_TEXT_PADDING;
+    let panel_height = lines.len() as u32 * LINE_HEIGHT + 2 * STENCIL_TEXT_PADDING;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    let margin = STENCIL_TEXT_PADDING as i32;
+    canvas.fill_rect(Rect::new(margin, margin, panel_width, panel_height))?;
+    canvas.set_blend_mode(BlendMode::None);
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            canvas,
+            2 * margin,
+            2 * margin + (i as u32 * LINE_HEIGHT) as i32,
+            line,
+            Color::RGB(255, 255, 255),
+        )?;
+    }
+    Ok(())
+}
+
+// A 1px line along every boundary between cells inside `target`, the window area the grid
+// was scaled into
+fn draw_cell_borders(
+    canvas: &mut Canvas<Window>,
+    target: Rect,
+    columns: u32,
+    rows: u32,
+) -> Result<(), String> {
+    let (left, top) = (target.x(), target.y());
+    let (right, bottom) = (left + target.width() as i32, top + target.height() as i32);
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, (BORDER_SHADE * 255.0) as u8));
+    for i in 1..columns {
+        let x = left + (i as Real * target.width() as Real / columns as Real).round() as i32;
+        canvas.draw_line((x, top), (x, bottom - 1))?;
+    }
+    for j in 1..rows {
+        let y = top + (j as Real * target.height() as Real / rows as Real).round() as i32;
+        canvas.draw_line((left, y), (right - 1, y))?;
+    }
+    canvas.set_blend_mode(BlendMode::None);
+    Ok(())
+}
+
+// Counts of the fluid cells' values in HISTOGRAM_BINS equal bins from the smallest to the
+// largest finite value, with the NaN and infinite cells counted on their own. Returns
+// (bins, off the chart, min, max).
+fn histogram(sim: &Simulation, field: HistogramField) -> (Vec<usize>, usize, Real, Real) {
+    let values: Vec<Real> = sim
+        .grid
+        .iter()
+        .flatten()
+        .zip(sim.solid.iter().flatten())
+        .filter(|(_, &solid)| !solid)
+        .map(|(cell, _)| match field {
+            HistogramField::Density => cell.density,
+            HistogramField::Pressure => calculate_pressure(cell, sim.settings.gamma),
+        })
+        .collect();
+    let finite = || values.iter().copied().filter(|v| v.is_finite());
+    let min = finite().fold(Real::INFINITY, Real::min);
+    let max = finite().fold(Real::NEG_INFINITY, Real::max);
+
+    let mut bins = vec![0; HISTOGRAM_BINS];
+    for value in finite() {
+        // A flat field lands entirely in the first bin
+        let t = if max > min {
+            (value - min) / (max - min)
+        } else {
+            0.0
+        };
+        bins[((t * HISTOGRAM_BINS as Real) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let off_chart = values.len() - bins.iter().sum::<usize>();
+    (bins, off_chart, min, max)
+}
+
+// Bars on a dimmed panel, with a red bar past the end for cells that are NaN or infinite.
+// Heights go with log(1 + count) so a handful of outliers still shows up next to the bulk.
+fn draw_histogram(
+    canvas: &mut Canvas<Window>,
+    sim: &Simulation,
+    field: HistogramField,
+) -> Result<(), String> {
+    let (bins, off_chart, min, max) = histogram(sim, field);
+    let tallest = bins
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(off_chart)
+        .max(1);
+    let (_, window_height) = canvas.output_size()?;
+    let panel_width = (HISTOGRAM_BINS as u32 + 2) * HISTOGRAM_BAR_WIDTH;
+    let left = HISTOGRAM_MARGIN as i32;
+    let bottom = window_height.saturating_sub(HISTOGRAM_MARGIN) as i32;
+
+    // The range goes in a line of text above the bars
+    let panel_height = HISTOGRAM_HEIGHT + LINE_HEIGHT + HISTOGRAM_TEXT_PADDING;
+    let top = bottom - panel_height as i32;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(Rect::new(left, top, panel_width, panel_height))?;
+    canvas.set_blend_mode(BlendMode::None);
+    draw_text(
+        canvas,
+        left + HISTOGRAM_TEXT_PADDING as i32,
+        top + HISTOGRAM_TEXT_PADDING as i32,
+        &format!("{:.1e}..{:.1e}", min, max),
+        Color::RGB(255, 255, 255),
+    )?;
+
+    let mut bar = |slot: usize, count: usize, color: Color| -> Result<(), String> {
+        let fraction = (count as Real).ln_1p() / (tallest as Real).ln_1p();
+        let height = (fraction * HISTOGRAM_HEIGHT as Real).round() as u32;
+        if height == 0 {
+            return Ok(());
+        }
+        canvas.set_draw_color(color);
+        canvas.fill_rect(Rect::new(
+            left + (slot as u32 * HISTOGRAM_BAR_WIDTH) as i32,
+            bottom - height as i32,
+            HISTOGRAM_BAR_WIDTH - 1,
+            height,
+        ))
+    };
+    for (slot, &count) in bins.iter().enumerate() {
+        bar(slot, count, Color::RGB(255, 255, 255))?;
+    }
+    bar(HISTOGRAM_BINS + 1, off_chart, Color::RGB(255, 60, 60))
+}
+
+// One line summary of the histogram's range for the window title
+pub fn histogram_label(sim: &Simulation, field: HistogramField) -> String {
+    let (_, off_chart, min, max) = histogram(sim, field);
+    let mut label = format!("{} {:.3e}..{:.3e}", field.name(), min, max);
+    if off_chart > 0 {
+        label += &format!(", {} off the chart", off_chart);
+    }
+    label
+}
+
+// The current mode's color scale: what it is, the values at either end and the color at
+// each step along it
+struct Colorbar {
+    name: &'static str,
+    min: Real,
+    max: Real,
+    colors: Vec<Color>,
+}
+
+// None for the modes that aren't a scale of one value
+fn colorbar(sim: &Simulation, view: &View) -> Option<Colorbar> {
+    let ambient = sim.settings.ambient().density;
+    let (field, scale) = mode_field(sim, view);
+    let (name, min, max, color): (_, _, _, Box<dyn Fn(Real) -> Color>) = match (&field, view.mode) {
+        (None, RenderMode::Anchored) => (
+            "density",
+            0.0,
+            view.color_max,
+            Box::new(|v| anchored_color(v, ambient, view.color_max)),
+        ),
+        (None, RenderMode::Tracers) if !sim.tracers.is_empty() => return None,
+        (None, RenderMode::Regions) => return None,
+        (None, _) => (
+            "density",
+            0.0,
+            DEFAULT_COLOR_MAX,
+            Box::new(|v| {
+                density_color(&Cell {
+                    density: v,
+                    ..Cell::zero()
+                })
+            }),
+        ),
+        (Some(_), RenderMode::Fluctuation) => (
+            "density fluctuation",
+            -scale,
+            scale,
+            Box::new(|v| diverging_color(v, scale)),
+        ),
+        (Some(_), RenderMode::Divergence) => (
+            "divergence",
+            -scale,
+            scale,
+            Box::new(|v| diverging_color(v, scale)),
+        ),
+        (Some(_), RenderMode::Schlieren) => (
+            "density gradient",
+            0.0,
+            scale,
+            Box::new(|v| schlieren_color(v, scale)),
+        ),
+        // At the ambient density, which is how still gas of it looks
+        (Some(_), RenderMode::DensityVorticity) => (
+            "vorticity",
+            -scale,
+            scale,
+            Box::new(|v| density_vorticity_color(ambient, ambient, view.color_max, v, scale)),
+        ),
+        (Some(_), RenderMode::MaxHold) => {
+            let floor = sim
+                .max_hold
+                .as_ref()
+                .map_or(0.0, |hold| held_floor(hold, &sim.solid));
+            (
+                "max hold",
+                floor,
+                floor + scale,
+                Box::new(move |v| heat_color((v - floor) / scale)),
+            )
+        }
+        (Some(_), mode) => (mode.name(), 0.0, scale, Box::new(|v| heat_color(v / scale))),
+    };
+    let colors = (0..COLORBAR_WIDTH)
+        .map(|i| color(min + (max - min) * (i as Real + 0.5) / COLORBAR_WIDTH as Real))
+        .collect();
+    Some(Colorbar {
+        name,
+        min,
+        max,
+        colors,
+    })
+}
+
+// The colorbar, with its range under it, and a bar as long as a round number of cells
+// labelled with how far that is, in a panel over the bottom right corner of `target`
+fn draw_legend<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    sim: &Simulation,
+    view: &View,
+    target: Rect,
+) -> Result<(), String> {
+    let scale = target.width() as Real / sim.grid[0].len() as Real;
+    let cells = SCALE_BAR_CELLS
+        .into_iter()
+        .find(|&n| n as Real * scale <= target.width() as Real / 3.0)
+        .unwrap_or(1);
+    let bar_length = (cells as Real * scale).round().max(1.0) as u32;
+    let distance = match sim.settings.units {
+        Units::Physical => format!("{} m", cells as Real * CELL_LENGTH),
+        Units::Legacy => format!("{} cells", cells),
+    };
+    let colorbar = colorbar(sim, view);
+    let range = colorbar
+        .as_ref()
+        .map(|bar| (format!("{:.2e}", bar.min), format!("{:.2e}", bar.max)));
+
+    let text_width = |text: &str| text.chars().count() as u32 * ADVANCE;
+    let mut inner_width = bar_length.max(text_width(&distance));
+    let mut inner_height = SCALE_BAR_HEIGHT + LEGEND_PADDING + LINE_HEIGHT;
+    if let (Some(bar), Some((min, max))) = (&colorbar, &range) {
+        inner_width = inner_width
+            .max(COLORBAR_WIDTH)
+            .max(text_width(bar.name))
+            .max(text_width(min) + ADVANCE + text_width(max));
+        inner_height += 2 * LINE_HEIGHT + COLORBAR_HEIGHT + 3 * LEGEND_PADDING;
+    }
+    let panel = Rect::new(
+        target.right() - (inner_width + 2 * LEGEND_PADDING + LEGEND_MARGIN) as i32,
+        target.bottom() - (inner_height + 2 * LEGEND_PADDING + LEGEND_MARGIN) as i32,
+        inner_width + 2 * LEGEND_PADDING,
+        inner_height + 2 * LEGEND_PADDING,
+    );
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(panel)?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    let white = Color::RGB(255, 255, 255);
+    let left = panel.x() + LEGEND_PADDING as i32;
+    let mut top = panel.y() + LEGEND_PADDING as i32;
+    if let (Some(bar), Some((min, max))) = (&colorbar, &range) {
+        draw_text(canvas, left, top, bar.name, white)?;
+        top += (LINE_HEIGHT + LEGEND_PADDING) as i32;
+        for (i, &color) in bar.colors.iter().enumerate() {
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(left + i as i32, top, 1, COLORBAR_HEIGHT))?;
+        }
+        top += (COLORBAR_HEIGHT + LEGEND_PADDING) as i32;
+        draw_text(canvas, left, top, min, white)?;
+        let right = left + inner_width as i32 - text_width(max) as i32;
+        draw_text(canvas, right, top, max, white)?;
+        top += (LINE_HEIGHT + LEGEND_PADDING) as i32;
+    }
+    canvas.set_draw_color(white);
+    canvas.fill_rect(Rect::new(left, top, bar_length, SCALE_BAR_HEIGHT))?;
+    top += (SCALE_BAR_HEIGHT + LEGEND_PADDING) as i32;
+    draw_text(canvas, left, top, &distance, white)
+}
+
+// Every face between a surface cell and the obstacle drawn over in the cell's Cp, red
+// where it's below the free stream's pressure and blue above, to the largest magnitude
+// on the surface, which goes in the top right corner. On the simulated half when
+// mirrored.
+fn draw_surface_cp<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    sim: &Simulation,
+    target: Rect,
+) -> Result<(), String> {
+    let Some(surface) = surface_cp(sim) else {
+        return Ok(());
+    };
+    let scale = target.width() as Real / sim.grid.width() as Real;
+    let extent = surface
+        .iter()
+        .map(|point| point.cp.abs())
+        .filter(|cp| cp.is_finite())
+        .fold(0.0, Real::max);
+    let thickness = (SURFACE_CP_WIDTH * scale).round().max(2.0);
+    for point in &surface {
+        canvas.set_draw_color(diverging_color(point.cp, extent));
+        canvas.fill_rects(&obstacle_faces(sim, point.x, point.y, target, thickness))?;
+    }
+    let label = format!("cp +-{:.2e}", extent);
+    let margin = LEGEND_MARGIN as i32;
+    let right = target.right() - margin - (label.chars().count() as u32 * ADVANCE) as i32;
+    draw_text(
+        canvas,
+        right,
+        target.y() + margin,
+        &label,
+        Color::RGB(255, 255, 255),
+    )
+}
+
+// Every face between the gas and the obstacle as a thin light line, for the outline view
+// that draws the obstacle's cells like the gas's. On the simulated half when mirrored.
+fn draw_obstacle_outline<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    sim: &Simulation,
+    target: Rect,
+) -> Result<(), String> {
+    let scale = target.width() as Real / sim.grid.width() as Real;
+    let thickness = (OUTLINE_WIDTH * scale).round().max(1.0);
+    canvas.set_draw_color(OUTLINE_COLOR);
+    for y in 0..sim.grid.height() {
+        for x in 0..sim.grid.width() {
+            if !sim.solid[y][x] {
+                canvas.fill_rects(&obstacle_faces(sim, x, y, target, thickness))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// An arrow along the velocity from the middle of one fluid cell in every `stride` along
+// each axis. Twice the mean speed of those drawn makes an arrow `stride` cells long, the
+// length going with the speed and faster cells clamped to it so arrows never run into
+// their neighbors. On the simulated half when mirrored.
+fn draw_arrows<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    sim: &Simulation,
+    target: Rect,
+    stride: usize,
+) -> Result<(), String> {
+    let scale = target.width() as Real / sim.grid.width() as Real;
+    let arrows: Vec<_> = (stride / 2..sim.grid.height())
+        .step_by(stride)
+        .flat_map(|y| {
+            (stride / 2..sim.grid.width())
+                .step_by(stride)
+                .map(move |x| (x, y))
+        })
+        .filter(|&(x, y)| !sim.solid[y][x])
+        .map(|(x, y)| (x, y, sim.cell_velocity(x, y)))
+        .filter(|(_, _, (vx, vy))| vx.is_finite() && vy.is_finite())
+        .collect();
+    let speeds = arrows.iter().map(|(_, _, (vx, vy))| vx.hypot(*vy));
+    let reference = 2.0 * speeds.sum::<Real>() / arrows.len().max(1) as Real;
+    if reference <= 0.0 {
+        return Ok(());
+    }
+    let longest = stride as Real * scale;
+    let point = |(px, py): (Real, Real)| Point::new(px.round() as i32, py.round() as i32);
+    canvas.set_draw_color(ARROW_COLOR);
+    for (x, y, (vx, vy)) in arrows {
+        let speed = vx.hypot(vy);
+        if speed == 0.0 {
+            continue;
+        }
+        let length = longest * (speed / reference).min(1.0);
+        let (dx, dy) = (vx / speed, vy / speed);
+        let start = (
+            target.x() as Real + (x as Real + 0.5) * scale,
+            target.y() as Real + (y as Real + 0.5) * scale,
+        );
+        let tip = (start.0 + dx * length, start.1 + dy * length);
+        canvas.draw_line(point(start), point(tip))?;
+        let head = length * ARROW_HEAD;
+        for side in [-1.0, 1.0] {
+            let back = (
+                tip.0 - dx * head - dy * side * head,
+                tip.1 - dy * head + dx * side * head,
+            );
+            canvas.draw_line(point(tip), point(back))?;
+        }
+    }
+    Ok(())
+}
+
+// The faces between the fluid cell (x, y) and the obstacle cells next to it, as strips
+// `thickness` pixels across centered on them in `target`
+fn obstacle_faces(
+    sim: &Simulation,
+    x: usize,
+    y: usize,
+    target: Rect,
+    thickness: Real,
+) -> Vec<Rect> {
+    let (width, height) = (sim.grid.width(), sim.grid.height());
+    let scale = target.width() as Real / width as Real;
+    let at = |cells: Real| cells * scale;
+    // The far side of the cell from a neighbor after it, wrapped around or not
+    let after = |n: usize, i: usize, cells: usize| n == (i + 1) % cells;
+    sim.solid
+        .neighbors(x, y, sim.settings.periodic)
+        .filter(|&(nx, ny)| sim.solid[ny][nx])
+        .map(|(nx, ny)| {
+            let (left, top, across, down) = if ny == y {
+                let edge = if after(nx, x, width) { x + 1 } else { x } as Real;
+                (
+                    at(edge) - thickness / 2.0,
+                    at(y as Real),
+                    thickness,
+                    at(1.0),
+                )
+            } else {
+                let edge = if after(ny, y, height) { y + 1 } else { y } as Real;
+                (
+                    at(x as Real),
+                    at(edge) - thickness / 2.0,
+                    at(1.0),
+                    thickness,
+                )
+            };
+            Rect::new(
+                target.x() + left.round() as i32,
+                target.y() + top.round() as i32,
+                across.round().max(1.0) as u32,
+                down.round().max(1.0) as u32,
+            )
+        })
+        .collect()
+}
+
+// RGB24 pixels with each cell drawn as a `cell_size` square, as (width, height, pixels)
+pub fn render_grid_to_buffer(sim: &Simulation, view: &View, cell_size: u32) -> (u32, u32, Vec<u8>) {
+    let mut colors = compute_colors(sim, view);
+    if view.mirror {
+        let reflection: Vec<_> = colors.iter().rev().cloned().collect();
+        colors.extend(reflection);
+    }
+    let width = sim.grid[0].len() as u32 * cell_size;
+    let height = colors.len() as u32 * cell_size;
+    let mut pixels = vec![0u8; (width * height * 3) as usize];
+
+    // With borders the last pixel row and column of each cell are darkened, which needs
+    // cells at least 2 pixels across
+    let borders = view.cell_borders && cell_size > 1;
+    let black = Color::RGB(0, 0, 0);
+    pixels
+        .par_chunks_mut(width as usize * 3)
+        .enumerate()
+        .for_each(|(py, line)| {
+            let y = py / cell_size as usize;
+            let border_row = py % cell_size as usize == cell_size as usize - 1;
+            for (px, pixel) in line.chunks_mut(3).enumerate() {
+                let mut color = colors[y][px / cell_size as usize];
+                if borders && (border_row || px % cell_size as usize == cell_size as usize - 1) {
+                    color = blend(color, black, BORDER_SHADE);
+                }
+                pixel.copy_from_slice(&[color.r, color.g, color.b]);
+            }
+        });
+
+    (width, height, pixels)
+}
+
+fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    image::save_buffer(path, pixels, width, height, image::ExtendedColorType::Rgb8)
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+pub fn save_canvas_png(canvas: &Canvas<Window>, path: &Path) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let pixels = canvas.read_pixels(None, PixelFormatEnum::RGB24)?;
+    save_png(path, width, height, &pixels)
+}
+
+pub fn save_grid_png(sim: &Simulation, view: &View, path: &Path) -> Result<(), String> {
+    let (width, height, pixels) = render_grid_to_buffer(sim, view, view.cell_size);
+    let pixels = with_overlays(sim, view, width, height, pixels)?;
+    save_png(path, width, height, &pixels)
+}
+
+// The legend and the surface Cp drawn into a rendered buffer too, when they're on, so
+// saved images carry them. SDL's software renderer only blends the legend's panel onto 4
+// byte pixels, so it draws on a copy with an alpha channel that's then dropped again.
+fn with_overlays(
+    sim: &Simulation,
+    view: &View,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    if !view.legend && !view.surface_cp && !view.obstacle_outline && !view.arrows {
+        return Ok(pixels);
+    }
+    let mut rgba: Vec<u8> = pixels
+        .chunks(3)
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect();
+    let surface = Surface::from_data(&mut rgba, width, height, width * 4, PixelFormatEnum::RGBA32)?;
+    let mut canvas = surface.into_canvas()?;
+    let target = Rect::new(0, 0, width, height);
+    if view.obstacle_outline {
+        draw_obstacle_outline(&mut canvas, sim, target)?;
+    }
+    if view.arrows {
+        draw_arrows(&mut canvas, sim, target, view.arrow_stride)?;
+    }
+    if view.surface_cp {
+        draw_surface_cp(&mut canvas, sim, target)?;
+    }
+    if view.legend {
+        draw_legend(&mut canvas, sim, view, target)?;
+    }
+    canvas.present();
+    drop(canvas);
+    Ok(rgba.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect())
+}
+
+// A looping GIF, encoded a frame at a time as the run goes. The encoder needs the frame
+// size, so it's only started on the first frame; every later one has to match it.
+struct GifRecording {
+    file: Option<BufWriter<File>>,
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    size: (u32, u32),
+    path: PathBuf,
+    delay: u16, // Hundredths of a second
+}
+
+impl GifRecording {
+    fn write(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+        let path = &self.path;
+        let failed = |e: gif::EncodingError| format!("failed to write {}: {}", path.display(), e);
+        if let Some(file) = self.file.take() {
+            if width > u16::MAX as u32 || height > u16::MAX as u32 {
+                return Err(format!("{}x{} is too big for a GIF", width, height));
+            }
+            let mut encoder =
+                gif::Encoder::new(file, width as u16, height as u16, &[]).map_err(failed)?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(failed)?;
+            self.encoder = Some(encoder);
+            self.size = (width, height);
+        }
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+        if self.size != (width, height) {
+            return Err(format!(
+                "the frame changed size, {} is {}x{}",
+                path.display(),
+                self.size.0,
+                self.size.1
+            ));
+        }
+        let mut frame = gif::Frame::from_rgb_speed(width as u16, height as u16, pixels, GIF_SPEED);
+        frame.delay = self.delay;
+        encoder.write_frame(&frame).map_err(failed)
+    }
+}
+
+// Writes every `every`th step as dir/frame_000001.png, dir/frame_000002.png, ... and as
+// the next frame of the GIF, whichever were asked for. Frames are counted as written, so
+// the sequence has no gaps whatever `every` is, and stop at `max` of them if there's a cap.
+pub struct Recorder {
+    dir: Option<PathBuf>,
+    gif: Option<GifRecording>,
+    every: u64,
+    max: Option<usize>,
+    first: usize, // Number of the first frame, past any an earlier run left in `dir`
+    written: usize,
+}
+
+// One past the highest frame_NNNNNN.png in `dir`, 1 for none
+fn next_frame_number(dir: &Path) -> Result<usize, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let number = name.strip_prefix("frame_")?.strip_suffix(".png")?;
+            number.parse::<usize>().ok()
+        })
+        .map(|number| number + 1)
+        .max()
+        .unwrap_or(1))
+}
+
+impl Recorder {
+    // `gif` is the path and how long each frame shows, in milliseconds. Appending numbers the
+    // frames on from the last one already in `dir`; a GIF can't be added to, so an existing
+    // one is left alone instead of overwritten.
+    pub fn new(
+        dir: Option<PathBuf>,
+        gif: Option<(PathBuf, u32)>,
+        every: u64,
+        max: Option<usize>,
+        append: bool,
+    ) -> Result<Recorder, String> {
+        let mut first = 1;
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+            // An existing directory can still be read only, better to find out before the run
+            // than at its first frame
+            let probe = dir.join(".fluid_write_test");
+            File::create(&probe)
+                .and_then(|_| std::fs::remove_file(&probe))
+                .map_err(|e| format!("could not write to {}: {}", dir.display(), e))?;
+            if append {
+                first = next_frame_number(dir)?;
+            }
+        }
+        let gif = match gif {
+            Some((path, _)) if append && path.exists() => {
+                return Err(format!(
+                    "{} already exists and a GIF can't be appended to",
+                    path.display()
+                ))
+            }
+            Some((path, delay)) => {
+                let file = File::create(&path)
+                    .map_err(|e| format!("could not create {}: {}", path.display(), e))?;
+                Some(GifRecording {
+                    file: Some(BufWriter::new(file)),
+                    encoder: None,
+                    size: (0, 0),
+                    path,
+                    delay: (delay / 10).clamp(1, u16::MAX as u32) as u16,
+                })
+            }
+            None => None,
+        };
+        Ok(Recorder {
+            dir,
+            gif,
+            every: every.max(1),
+            max,
+            first,
+            written: 0,
+        })
+    }
+
+    pub fn capture(&mut self, sim: &Simulation, view: &View) -> Result<(), String> {
+        if !sim.step_count.is_multiple_of(self.every) || self.is_full() {
+            return Ok(());
+        }
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("frame_{:06}.png", self.first + self.written));
+            save_grid_png(sim, view, &path)?;
+        }
+        if let Some(gif) = &mut self.gif {
+            let (width, height, pixels) = render_grid_to_buffer(sim, view, GIF_CELL_SIZE);
+            let pixels = with_overlays(sim, view, width, height, pixels)?;
+            gif.write(width, height, &pixels)?;
+        }
+        self.written += 1;
+        if self.is_full() {
+            println!(
+                "Stopped recording at step {} after {} frames",
+                sim.step_count, self.written
+            );
+        }
+        Ok(())
+    }
+
+    fn is_full(&self) -> bool {
+        self.max.is_some_and(|max| self.written >= max)
+    }
+
+    // Says where the frames went. The GIF is finished off when the recorder is dropped.
+    pub fn report(&self) {
+        if let Some(dir) = &self.dir {
+            println!("Recorded {} frames to {}", self.written, dir.display());
+        }
+        if let Some(gif) = &self.gif {
+            println!("Recorded {} frames to {}", self.written, gif.path.display());
+        }
+    }
+}
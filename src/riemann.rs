@@ -0,0 +1,245 @@
+use crate::Real;
+use serde::{Deserialize, Serialize};
+
+// Newton iteration on the star pressure stops once the relative change drops below this
+const PRESSURE_TOLERANCE: Real = 1e-12;
+const MAX_ITERATIONS: usize = 100;
+
+// Primitive gas state in one dimension
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub density: Real,
+    pub velocity: Real,
+    pub pressure: Real,
+}
+
+impl State {
+    fn sound_speed(&self, gamma: Real) -> Real {
+        (gamma * self.pressure / self.density).sqrt()
+    }
+}
+
+// Sod's shock tube: dense high-pressure gas on the left, thin low-pressure gas on the right
+pub const SOD_LEFT: State = State {
+    density: 1.0,
+    velocity: 0.0,
+    pressure: 1.0,
+};
+pub const SOD_RIGHT: State = State {
+    density: 0.125,
+    velocity: 0.0,
+    pressure: 0.1,
+};
+
+// The exact solution of the Riemann problem between `left` and `right`, following Toro,
+// "Riemann Solvers and Numerical Methods for Fluid Dynamics", chapter 4. It's
+// self-similar, so it only depends on xi = (x - x0) / t. The two sides can be different
+// gases, each wave only ever seeing the gas it runs into.
+pub struct ExactRiemann {
+    left: State,
+    right: State,
+    gamma_left: Real,
+    gamma_right: Real,
+    pressure_star: Real,
+    velocity_star: Real,
+}
+
+impl ExactRiemann {
+    pub fn new(left: State, right: State, gamma: Real) -> ExactRiemann {
+        ExactRiemann::two_gases(left, right, gamma, gamma)
+    }
+
+    pub fn two_gases(
+        left: State,
+        right: State,
+        gamma_left: Real,
+        gamma_right: Real,
+    ) -> ExactRiemann {
+        let du = right.velocity - left.velocity;
+        let mut pressure = (0.5 * (left.pressure + right.pressure)).max(PRESSURE_TOLERANCE);
+        for _ in 0..MAX_ITERATIONS {
+            let (f_left, df_left) = pressure_function(pressure, &left, gamma_left);
+            let (f_right, df_right) = pressure_function(pressure, &right, gamma_right);
+            let next =
+                (pressure - (f_left + f_right + du) / (df_left + df_right)).max(PRESSURE_TOLERANCE);
+            let change = 2.0 * (next - pressure).abs() / (next + pressure);
+            pressure = next;
+            if change < PRESSURE_TOLERANCE {
+                break;
+            }
+        }
+        let (f_left, _) = pressure_function(pressure, &left, gamma_left);
+        let (f_right, _) = pressure_function(pressure, &right, gamma_right);
+
+        ExactRiemann {
+            left,
+            right,
+            gamma_left,
+            gamma_right,
+            pressure_star: pressure,
+            velocity_star: 0.5 * (left.velocity + right.velocity) + 0.5 * (f_right - f_left),
+        }
+    }
+
+    // Speed and the density behind it of the wave running into the right state, if that
+    // is a shock. From the Rankine-Hugoniot conditions across it.
+    pub fn right_shock(&self) -> Option<(Real, Real)> {
+        let (g, r) = (self.gamma_right, self.right);
+        let ratio = self.pressure_star / r.pressure;
+        if ratio <= 1.0 {
+            return None;
+        }
+        let gm = (g - 1.0) / (g + 1.0);
+        let speed = r.velocity
+            + r.sound_speed(g) * ((g + 1.0) / (2.0 * g) * ratio + (g - 1.0) / (2.0 * g)).sqrt();
+        Some((speed, r.density * (ratio + gm) / (gm * ratio + 1.0)))
+    }
+
+    // How fast the contact between the two gases moves
+    pub fn contact_speed(&self) -> Real {
+        self.velocity_star
+    }
+
+    pub fn sample(&self, xi: Real) -> State {
+        let (p_star, u_star) = (self.pressure_star, self.velocity_star);
+
+        if xi <= u_star {
+            let g = self.gamma_left;
+            let gm = (g - 1.0) / (g + 1.0);
+            let l = self.left;
+            let a = l.sound_speed(g);
+            let ratio = p_star / l.pressure;
+            if p_star > l.pressure {
+                // Left shock
+                let speed =
+                    l.velocity - a * ((g + 1.0) / (2.0 * g) * ratio + (g - 1.0) / (2.0 * g)).sqrt();
+                if xi <= speed {
+                    l
+                } else {
+                    State {
+                        density: l.density * (ratio + gm) / (gm * ratio + 1.0),
+                        velocity: u_star,
+                        pressure: p_star,
+                    }
+                }
+            } else {
+                // Left rarefaction
+                let head = l.velocity - a;
+                let tail = u_star - a * ratio.powf((g - 1.0) / (2.0 * g));
+                if xi <= head {
+                    l
+                } else if xi > tail {
+                    State {
+                        density: l.density * ratio.powf(1.0 / g),
+                        velocity: u_star,
+                        pressure: p_star,
+                    }
+                } else {
+                    let c = 2.0 / (g + 1.0) * (a + (g - 1.0) / 2.0 * (l.velocity - xi));
+                    State {
+                        density: l.density * (c / a).powf(2.0 / (g - 1.0)),
+                        velocity: 2.0 / (g + 1.0) * (a + (g - 1.0) / 2.0 * l.velocity + xi),
+                        pressure: l.pressure * (c / a).powf(2.0 * g / (g - 1.0)),
+                    }
+                }
+            }
+        } else {
+            let g = self.gamma_right;
+            let r = self.right;
+            let a = r.sound_speed(g);
+            let ratio = p_star / r.pressure;
+            if let Some((speed, density)) = self.right_shock() {
+                if xi >= speed {
+                    r
+                } else {
+                    State {
+                        density,
+                        velocity: u_star,
+                        pressure: p_star,
+                    }
+                }
+            } else {
+                // Right rarefaction
+                let head = r.velocity + a;
+                let tail = u_star + a * ratio.powf((g - 1.0) / (2.0 * g));
+                if xi >= head {
+                    r
+                } else if xi <= tail {
+                    State {
+                        density: r.density * ratio.powf(1.0 / g),
+                        velocity: u_star,
+                        pressure: p_star,
+                    }
+                } else {
+                    let c = 2.0 / (g + 1.0) * (a - (g - 1.0) / 2.0 * (r.velocity - xi));
+                    State {
+                        density: r.density * (c / a).powf(2.0 / (g - 1.0)),
+                        velocity: 2.0 / (g + 1.0) * (-a + (g - 1.0) / 2.0 * r.velocity + xi),
+                        pressure: r.pressure * (c / a).powf(2.0 * g / (g - 1.0)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Velocity jump across the wave on one side for a trial star pressure, and its derivative
+fn pressure_function(pressure: Real, state: &State, gamma: Real) -> (Real, Real) {
+    let a = state.sound_speed(gamma);
+    if pressure > state.pressure {
+        let big_a = 2.0 / ((gamma + 1.0) * state.density);
+        let big_b = (gamma - 1.0) / (gamma + 1.0) * state.pressure;
+        let root = (big_a / (pressure + big_b)).sqrt();
+        (
+            (pressure - state.pressure) * root,
+            root * (1.0 - (pressure - state.pressure) / (2.0 * (big_b + pressure))),
+        )
+    } else {
+        let ratio = pressure / state.pressure;
+        (
+            2.0 * a / (gamma - 1.0) * (ratio.powf((gamma - 1.0) / (2.0 * gamma)) - 1.0),
+            ratio.powf(-(gamma + 1.0) / (2.0 * gamma)) / (state.density * a),
+        )
+    }
+}
+
+// Points scanned between the Mach angle and a right angle for the largest deflection, and
+// bisection steps after that
+const OBLIQUE_SCAN_POINTS: usize = 1000;
+const OBLIQUE_BISECTIONS: usize = 60;
+
+// How far an oblique shock at `beta` turns a Mach `mach` stream, the theta-beta-M relation
+// tan theta = 2 cot beta (M^2 sin^2 beta - 1) / (M^2 (gamma + cos 2 beta) + 2). Radians.
+fn deflection(mach: Real, beta: Real, gamma: Real) -> Real {
+    let m2 = mach * mach;
+    let sin = beta.sin();
+    (2.0 / beta.tan() * (m2 * sin * sin - 1.0) / (m2 * (gamma + (2.0 * beta).cos()) + 2.0)).atan()
+}
+
+// The weak attached shock turning a Mach `mach` stream through `theta`, both angles in
+// radians: the root of the theta-beta-M relation between the Mach angle and the angle of
+// largest deflection. None for a subsonic stream, or a turn too sharp for any attached
+// shock, which then stands off the body.
+pub fn oblique_shock_angle(mach: Real, theta: Real, gamma: Real) -> Option<Real> {
+    if !(mach > 1.0 && theta > 0.0) {
+        return None;
+    }
+    let mach_angle = (1.0 / mach).asin();
+    let span = crate::consts::FRAC_PI_2 - mach_angle;
+    let strongest = (0..=OBLIQUE_SCAN_POINTS)
+        .map(|i| mach_angle + span * i as Real / OBLIQUE_SCAN_POINTS as Real)
+        .max_by(|&a, &b| deflection(mach, a, gamma).total_cmp(&deflection(mach, b, gamma)))?;
+    if deflection(mach, strongest, gamma) < theta {
+        return None;
+    }
+    let (mut low, mut high) = (mach_angle, strongest);
+    for _ in 0..OBLIQUE_BISECTIONS {
+        let middle = (low + high) / 2.0;
+        if deflection(mach, middle, gamma) < theta {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some((low + high) / 2.0)
+}
@@ -0,0 +1,171 @@
+use crate::error::{AppError, Context};
+use crate::render::{save_grid_png, View};
+use crate::scenario::Scenario;
+use crate::shape::Shape;
+use crate::{next_free_path, seed_hotspots, Hotspot, Real, Simulation};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Something done to a run once the sim time reaches `at`, for demos that play out the
+// same way every time and setups that change partway through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptEvent {
+    pub at: Real,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Action {
+    // Sets a cell the way a starting hotspot does
+    Hotspot(Hotspot),
+    // Pushes the gas out from (x, y), in cells, like a left click
+    Poke { x: Real, y: Real, radius: Real },
+    // In the scenario's units, the same as its own shapes
+    AddObstacle { shape: Shape },
+    RemoveObstacle,
+    // Stops the scenario's jet or starts it again
+    Jet { on: bool },
+    Reverse,
+    // The next free script_NNNN.png without a path
+    Screenshot { path: Option<PathBuf> },
+}
+
+// What a --script file holds, a list of [[events]] tables like a scenario's
+#[derive(Deserialize)]
+struct ScriptFile {
+    #[serde(default)]
+    events: Vec<ScriptEvent>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<ScriptEvent>, AppError> {
+    let text = std::fs::read_to_string(path)
+        .context(&format!("Could not read script {}", path.display()))?;
+    let file: ScriptFile =
+        toml::from_str(&text).context(&format!("Invalid script {}", path.display()))?;
+    Ok(file.events)
+}
+
+// What's wrong with the events for a scenario, if anything
+pub fn problem(scenario: &Scenario) -> Option<String> {
+    let (width, height) = (scenario.width, scenario.height);
+    for event in &scenario.events {
+        if !(event.at.is_finite() && event.at >= 0.0) {
+            return Some(format!(
+                "script events need a time of 0 or more, got {}",
+                event.at
+            ));
+        }
+        let outside = match event.action {
+            Action::Hotspot(spot) => spot.x >= width || spot.y >= height,
+            Action::Poke { x, y, .. } => {
+                !(x >= 0.0 && y >= 0.0 && x < width as Real && y < height as Real)
+            }
+            Action::Jet { on: true } if scenario.source.is_none() => {
+                return Some(format!(
+                    "the script turns the jet on at t = {}, but the scenario has none",
+                    event.at
+                ));
+            }
+            _ => false,
+        };
+        if outside {
+            return Some(format!(
+                "the script's {} at t = {} is outside the {}x{} grid",
+                event.action.name(),
+                event.at,
+                width,
+                height
+            ));
+        }
+    }
+    None
+}
+
+impl Action {
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Hotspot(_) => "hotspot",
+            Action::Poke { .. } => "poke",
+            Action::AddObstacle { .. } => "add-obstacle",
+            Action::RemoveObstacle => "remove-obstacle",
+            Action::Jet { .. } => "jet",
+            Action::Reverse => "reverse",
+            Action::Screenshot { .. } => "screenshot",
+        }
+    }
+}
+
+// Works through the events in time order as the run passes them. Anything due happens
+// between steps, the first time the sim time is at or past it.
+pub struct Script {
+    events: Vec<ScriptEvent>,
+    next: usize,
+}
+
+impl Script {
+    pub fn new(events: &[ScriptEvent]) -> Script {
+        let mut events = events.to_vec();
+        events.sort_by(|a, b| a.at.total_cmp(&b.at));
+        Script { events, next: 0 }
+    }
+
+    // Obstacle changes go into `scenario` too, so exports and Tab carry on from them
+    pub fn run(
+        &mut self,
+        sim: &mut Simulation,
+        scenario: &mut Scenario,
+        view: &View,
+    ) -> Result<(), AppError> {
+        while let Some(event) = self.events.get(self.next) {
+            if event.at > sim.sim_time {
+                break;
+            }
+            self.next += 1;
+            match &event.action {
+                Action::Hotspot(spot) => seed_hotspots(&mut sim.grid, &[*spot]),
+                &Action::Poke { x, y, radius } => sim.poke(x, y, radius),
+                Action::AddObstacle { shape } => {
+                    scenario.shapes.push(*shape);
+                    sim.set_obstacle(scenario.solid_fraction()?);
+                }
+                Action::RemoveObstacle => {
+                    scenario.obstacle.clear();
+                    scenario.obstacle_svg = None;
+                    scenario.obstacle_png = None;
+                    scenario.shapes.clear();
+                    sim.set_obstacle(scenario.solid_fraction()?);
+                }
+                &Action::Jet { on } => {
+                    sim.settings.source = if on { scenario.source } else { None };
+                }
+                Action::Reverse => sim.reverse(),
+                Action::Screenshot { path } => {
+                    let path = path
+                        .clone()
+                        .unwrap_or_else(|| next_free_path("script", "png"));
+                    save_grid_png(sim, view, &path).context("Script screenshot failed")?;
+                    println!("Saved screenshot to {}", path.display());
+                }
+            }
+            println!(
+                "Script: {} at step {}, t = {:.4}",
+                event.action.name(),
+                sim.step_count,
+                sim.sim_time
+            );
+        }
+        Ok(())
+    }
+
+    pub fn report(&self) {
+        let left = self.events.len() - self.next;
+        if left > 0 {
+            println!(
+                "Script: the run ended before {} of its {} events",
+                left,
+                self.events.len()
+            );
+        }
+    }
+}
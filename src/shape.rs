@@ -0,0 +1,238 @@
+use crate::Real;
+use serde::{Deserialize, Serialize};
+
+// Samples per cell along each axis when rasterizing, so 16 per cell
+pub const SUPERSAMPLE: usize = 4;
+
+// Where a point given as fractions of the grid, 0 to 1 across the width and down the
+// height, lands in cells
+pub fn norm_to_cell(fx: Real, fy: Real, width: usize, height: usize) -> (Real, Real) {
+    (fx * width as Real, fy * height as Real)
+}
+
+// Obstacle outlines in cell units, (0, 0) being the top left corner of the grid
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Shape {
+    Rectangle {
+        x: Real,
+        y: Real,
+        width: Real,
+        height: Real,
+    },
+    Ellipse {
+        center_x: Real,
+        center_y: Real,
+        radius_x: Real,
+        radius_y: Real,
+    },
+    // Turned counterclockwise (on screen) by `angle` degrees about its center
+    RotatedRectangle {
+        center_x: Real,
+        center_y: Real,
+        width: Real,
+        height: Real,
+        angle: Real,
+    },
+    // Corners as [x, y], in any order
+    Triangle {
+        points: [[Real; 2]; 3],
+    },
+    // A NACA 4-digit section centered on the middle of its chord, nose to the left and
+    // pitched nose up by `angle` degrees. The camber, where along the chord it peaks and
+    // the thickness are fractions of the chord, 2412 being 0.02, 0.4 and 0.12.
+    Airfoil {
+        center_x: Real,
+        center_y: Real,
+        chord: Real,
+        camber: Real,
+        camber_position: Real,
+        thickness: Real,
+        angle: Real,
+    },
+}
+
+impl Shape {
+    // The shape read in fractions of the grid instead of cells: its position through
+    // norm_to_cell, its size as a fraction of the height along both axes so a circle
+    // stays round whatever the grid's aspect ratio
+    pub fn scaled(&self, width: usize, height: usize) -> Shape {
+        let size = height as Real;
+        match *self {
+            Shape::Rectangle {
+                x,
+                y,
+                width: w,
+                height: h,
+            } => {
+                let (x, y) = norm_to_cell(x, y, width, height);
+                Shape::Rectangle {
+                    x,
+                    y,
+                    width: w * size,
+                    height: h * size,
+                }
+            }
+            Shape::Ellipse {
+                center_x,
+                center_y,
+                radius_x,
+                radius_y,
+            } => {
+                let (center_x, center_y) = norm_to_cell(center_x, center_y, width, height);
+                Shape::Ellipse {
+                    center_x,
+                    center_y,
+                    radius_x: radius_x * size,
+                    radius_y: radius_y * size,
+                }
+            }
+            Shape::RotatedRectangle {
+                center_x,
+                center_y,
+                width: w,
+                height: h,
+                angle,
+            } => {
+                let (center_x, center_y) = norm_to_cell(center_x, center_y, width, height);
+                Shape::RotatedRectangle {
+                    center_x,
+                    center_y,
+                    width: w * size,
+                    height: h * size,
+                    angle,
+                }
+            }
+            Shape::Triangle { points } => Shape::Triangle {
+                points: points.map(|[x, y]| {
+                    let (x, y) = norm_to_cell(x, y, width, height);
+                    [x, y]
+                }),
+            },
+            Shape::Airfoil {
+                center_x,
+                center_y,
+                chord,
+                camber,
+                camber_position,
+                thickness,
+                angle,
+            } => {
+                let (center_x, center_y) = norm_to_cell(center_x, center_y, width, height);
+                Shape::Airfoil {
+                    center_x,
+                    center_y,
+                    chord: chord * size,
+                    camber,
+                    camber_position,
+                    thickness,
+                    angle,
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, px: Real, py: Real) -> bool {
+        match *self {
+            Shape::Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } => px >= x && px < x + width && py >= y && py < y + height,
+            Shape::Ellipse {
+                center_x,
+                center_y,
+                radius_x,
+                radius_y,
+            } => ((px - center_x) / radius_x).powi(2) + ((py - center_y) / radius_y).powi(2) < 1.0,
+            Shape::RotatedRectangle {
+                center_x,
+                center_y,
+                width,
+                height,
+                angle,
+            } => {
+                // Rotate the point back into the rectangle's own frame. y grows downward,
+                // so a positive angle on screen is a negative one in these coordinates.
+                let (sin, cos) = (-angle.to_radians()).sin_cos();
+                let (dx, dy) = (px - center_x, py - center_y);
+                let u = dx * cos + dy * sin;
+                let v = -dx * sin + dy * cos;
+                u.abs() < width / 2.0 && v.abs() < height / 2.0
+            }
+            Shape::Triangle { points } => {
+                // Inside when the point is on the same side of all three edges, whichever
+                // way round the corners go
+                let side = |[ax, ay]: [Real; 2], [bx, by]: [Real; 2]| {
+                    (bx - ax) * (py - ay) - (by - ay) * (px - ax)
+                };
+                let sides = [
+                    side(points[0], points[1]),
+                    side(points[1], points[2]),
+                    side(points[2], points[0]),
+                ];
+                sides.iter().all(|&s| s > 0.0) || sides.iter().all(|&s| s < 0.0)
+            }
+            Shape::Airfoil {
+                center_x,
+                center_y,
+                chord,
+                camber,
+                camber_position,
+                thickness,
+                angle,
+            } => {
+                // Into the foil's frame like the rotated rectangle, then in chords from the
+                // nose with y up. Pitching nose up turns it clockwise on screen.
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let (dx, dy) = (px - center_x, py - center_y);
+                let u = (dx * cos + dy * sin) / chord + 0.5;
+                let v = (dx * sin - dy * cos) / chord;
+                if !(0.0..=1.0).contains(&u) {
+                    return false;
+                }
+                let half = 5.0
+                    * thickness
+                    * (0.2969 * u.sqrt() - 0.1260 * u - 0.3516 * u * u + 0.2843 * u.powi(3)
+                        - 0.1015 * u.powi(4));
+                let (m, p) = (camber, camber_position);
+                let mean = if m <= 0.0 || p <= 0.0 || p >= 1.0 {
+                    0.0
+                } else if u < p {
+                    m / (p * p) * (2.0 * p * u - u * u)
+                } else {
+                    m / ((1.0 - p) * (1.0 - p)) * (1.0 - 2.0 * p + 2.0 * p * u - u * u)
+                };
+                // The thickness is laid off straight up and down from the camber line rather
+                // than along its normal, which only shifts a cambered nose by a hair
+                (v - mean).abs() < half
+            }
+        }
+    }
+
+    // Fraction of every cell the shape covers, from a SUPERSAMPLE x SUPERSAMPLE grid of
+    // sample points. Cells fully inside come out as exactly 1.0.
+    pub fn coverage(&self, width: usize, height: usize) -> Vec<Vec<Real>> {
+        let samples = (SUPERSAMPLE * SUPERSAMPLE) as Real;
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let mut inside = 0;
+                        for sy in 0..SUPERSAMPLE {
+                            for sx in 0..SUPERSAMPLE {
+                                let px = x as Real + (sx as Real + 0.5) / SUPERSAMPLE as Real;
+                                let py = y as Real + (sy as Real + 0.5) / SUPERSAMPLE as Real;
+                                if self.contains(px, py) {
+                                    inside += 1;
+                                }
+                            }
+                        }
+                        inside as Real / samples
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::{AppError, Context};
+use crate::grid::Grid;
+use crate::{Cell, Real};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+const HEADER: &str = "x,y,density,momentum_x,momentum_y,energy";
+
+// Numbers are written with `{}`, the shortest form that parses back to the same number,
+// so a dump loaded with `load_csv` reproduces the grid bit for bit
+pub fn save_csv(grid: &[Vec<Cell>], path: &Path) -> Result<(), AppError> {
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for (y, row) in grid.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                x, y, cell.density, cell.momentum_x, cell.momentum_y, cell.energy
+            );
+        }
+    }
+    std::fs::write(path, out).context(&format!("Could not write {}", path.display()))
+}
+
+fn line_error(path: &Path, line: usize, message: String) -> AppError {
+    AppError(format!("{} line {}: {}", path.display(), line + 1, message))
+}
+
+// Every cell a dump lists, with the line it's on
+fn read_csv(path: &Path) -> Result<Vec<(usize, usize, usize, Cell)>, AppError> {
+    let text =
+        std::fs::read_to_string(path).context(&format!("Could not read {}", path.display()))?;
+    let error = |line: usize, message: String| line_error(path, line, message);
+
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == HEADER => {}
+        _ => return Err(error(0, format!("expected the header `{}`", HEADER))),
+    }
+
+    let mut cells = Vec::new();
+    for (n, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != 6 {
+            return Err(error(n, format!("expected 6 fields, got {}", fields.len())));
+        }
+        let x: usize = fields[0]
+            .parse()
+            .map_err(|_| error(n, format!("bad x `{}`", fields[0])))?;
+        let y: usize = fields[1]
+            .parse()
+            .map_err(|_| error(n, format!("bad y `{}`", fields[1])))?;
+        let mut values = [0.0; 4];
+        for (value, field) in values.iter_mut().zip(&fields[2..]) {
+            *value = field
+                .parse()
+                .map_err(|_| error(n, format!("bad number `{}`", field)))?;
+        }
+        let [density, momentum_x, momentum_y, energy] = values;
+        let cell = Cell {
+            density,
+            momentum_x,
+            momentum_y,
+            energy,
+        };
+        cells.push((n, x, y, cell));
+    }
+    Ok(cells)
+}
+
+// Overwrites the cells listed in the file, the rest keep their current state
+pub fn load_csv(grid: &mut [Vec<Cell>], path: &Path) -> Result<(), AppError> {
+    let (width, height) = (grid.width(), grid.height());
+    for (n, x, y, cell) in read_csv(path)? {
+        let outside = || {
+            line_error(
+                path,
+                n,
+                format!(
+                    "cell ({}, {}) is outside the {}x{} grid",
+                    x, y, width, height
+                ),
+            )
+        };
+        *grid.at_mut(x, y).ok_or_else(outside)? = cell;
+    }
+    Ok(())
+}
+
+// Compares two dumps cell by cell: for each variable the largest difference and where it
+// is, and the L2 norm of the difference over the cells both list. For telling whether two
+// runs really did the same thing, and where they first part ways if not. NaN against a
+// number counts as the largest difference there is.
+pub fn diff_csv(first: &Path, second: &Path) -> Result<(), AppError> {
+    let index = |path: &Path| -> Result<BTreeMap<(usize, usize), Cell>, AppError> {
+        Ok(read_csv(path)?
+            .into_iter()
+            .map(|(_, x, y, cell)| ((y, x), cell))
+            .collect())
+    };
+    let (a, b) = (index(first)?, index(second)?);
+    let only_one = a.keys().filter(|key| !b.contains_key(key)).count()
+        + b.keys().filter(|key| !a.contains_key(key)).count();
+
+    let mut max: [(Real, Option<(usize, usize)>); 4] = [(0.0, None); 4];
+    let mut l2 = [0.0; 4];
+    let mut count = 0;
+    for (&(y, x), cell_a) in &a {
+        let Some(cell_b) = b.get(&(y, x)) else {
+            continue;
+        };
+        count += 1;
+        let values = |cell: &Cell| [cell.density, cell.momentum_x, cell.momentum_y, cell.energy];
+        for (i, (va, vb)) in values(cell_a).into_iter().zip(values(cell_b)).enumerate() {
+            // Bit for bit, so two NaNs agree and an identical run reports nothing
+            let difference = if va.to_bits() == vb.to_bits() {
+                0.0
+            } else if va.is_nan() || vb.is_nan() {
+                Real::INFINITY
+            } else {
+                (va - vb).abs()
+            };
+            if difference > max[i].0 {
+                max[i] = (difference, Some((x, y)));
+            }
+            l2[i] += difference * difference;
+        }
+    }
+
+    println!(
+        "Diff of {} and {}: {} cells in both, {} in only one",
+        first.display(),
+        second.display(),
+        count,
+        only_one
+    );
+    if count == 0 {
+        return Ok(());
+    }
+    let names = ["density", "momentum_x", "momentum_y", "energy"];
+    for (i, name) in names.iter().enumerate() {
+        let (largest, at) = max[i];
+        match at {
+            Some((x, y)) => println!(
+                "  {:<10}  max = {:.4e} at ({}, {})  L2 = {:.4e}",
+                name,
+                largest,
+                x,
+                y,
+                (l2[i] / count as Real).sqrt()
+            ),
+            None => println!("  {:<10}  identical", name),
+        }
+    }
+    Ok(())
+}
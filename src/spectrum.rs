@@ -0,0 +1,90 @@
+use crate::consts::PI;
+use crate::Real;
+
+// Fewest evenly spaced points a signal is resampled onto, fewer samples than this and
+// there's no spectrum worth reading
+const MIN_POINTS: usize = 64;
+
+// The strongest frequency in a signal sampled at the increasing `times`, zero aside. The
+// record is resampled linearly onto the largest power of two of even steps it has samples
+// for, less its mean and with a Hann window over it, and the peak of the power spectrum
+// found to within a fraction of a bin by fitting a parabola through it and its neighbors.
+pub fn dominant_frequency(times: &[Real], values: &[Real]) -> Option<Real> {
+    if times.len() < MIN_POINTS || times.len() != values.len() {
+        return None;
+    }
+    let points = 1 << times.len().ilog2();
+    let (start, end) = (times[0], times[times.len() - 1]);
+    let spacing = (end - start) / points as Real;
+    if !(spacing.is_finite() && spacing > 0.0) {
+        return None;
+    }
+    let mut re = Vec::with_capacity(points);
+    let mut sample = 0;
+    for i in 0..points {
+        let t = start + i as Real * spacing;
+        while sample + 2 < times.len() && times[sample + 1] < t {
+            sample += 1;
+        }
+        let (t0, t1) = (times[sample], times[sample + 1]);
+        let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 };
+        re.push(values[sample] + (values[sample + 1] - values[sample]) * f.clamp(0.0, 1.0));
+    }
+    let mean = re.iter().sum::<Real>() / points as Real;
+    for (i, value) in re.iter_mut().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * i as Real / points as Real).cos();
+        *value = (*value - mean) * window;
+    }
+    let mut im = vec![0.0; points];
+    fft(&mut re, &mut im);
+    let power: Vec<Real> = re
+        .iter()
+        .zip(&im)
+        .take(points / 2)
+        .map(|(r, i)| r * r + i * i)
+        .collect();
+    let peak = (1..power.len() - 1).max_by(|&a, &b| power[a].total_cmp(&power[b]))?;
+    let (before, at, after) = (power[peak - 1], power[peak], power[peak + 1]);
+    let curvature = before - 2.0 * at + after;
+    let offset = if curvature < 0.0 {
+        0.5 * (before - after) / curvature
+    } else {
+        0.0
+    };
+    Some((peak as Real + offset) / (points as Real * spacing))
+}
+
+// In place radix-2 Cooley-Tukey, the length a power of two
+fn fft(re: &mut [Real], im: &mut [Real]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as Real;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (w_re, w_im) = ((angle * k as Real).cos(), (angle * k as Real).sin());
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        length <<= 1;
+    }
+}
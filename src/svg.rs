@@ -0,0 +1,469 @@
+use crate::error::{AppError, Context};
+use crate::shape::SUPERSAMPLE;
+use crate::Real;
+use std::path::Path;
+
+// Straight segments each Bezier curve or arc is flattened into
+const CURVE_SEGMENTS: usize = 16;
+
+type Point = (Real, Real);
+
+// Fraction of every cell covered by the first <path> of an SVG file, sampled like
+// Shape::coverage. The viewBox (or the width and height without one) is fitted into the
+// grid keeping its aspect ratio and centered, as SVG viewers do by default. The path's
+// fill-rule picks between the nonzero and even-odd winding rules; transforms and any other
+// elements are ignored.
+pub fn load_solid_from_svg(
+    path: &Path,
+    width: usize,
+    height: usize,
+) -> Result<Vec<Vec<Real>>, AppError> {
+    let text = std::fs::read_to_string(path)
+        .context(&format!("Could not read obstacle SVG {}", path.display()))?;
+    let error = |message: &str| AppError(format!("obstacle SVG {}: {}", path.display(), message));
+
+    let svg = element(&text, "svg").ok_or_else(|| error("no <svg> element"))?;
+    let view_box = match attribute(svg, "viewBox") {
+        Some(view_box) => {
+            let mut lexer = Lexer::new(view_box);
+            let mut values = [0.0; 4];
+            for value in &mut values {
+                *value = lexer
+                    .number()
+                    .map_err(|e| error(&format!("viewBox: {}", e)))?;
+            }
+            values
+        }
+        None => {
+            let length = |name: &str| {
+                attribute(svg, name)
+                    .and_then(|value| Lexer::new(value).number().ok())
+                    .ok_or_else(|| error(&format!("no viewBox and no {}", name)))
+            };
+            [0.0, 0.0, length("width")?, length("height")?]
+        }
+    };
+    let [min_x, min_y, box_width, box_height] = view_box;
+    if !(box_width > 0.0 && box_height > 0.0) {
+        return Err(error("the viewBox is empty"));
+    }
+
+    let element_text = element(&text, "path").ok_or_else(|| error("no <path> element"))?;
+    if text.matches("<path").count() > 1 {
+        println!(
+            "{} has more than one <path>, only the first is used",
+            path.display()
+        );
+    }
+    let data = attribute(element_text, "d").ok_or_else(|| error("the <path> has no `d`"))?;
+    let polygons = parse_path(data).map_err(|e| error(&e))?;
+    let even_odd = attribute(element_text, "fill-rule") == Some("evenodd");
+
+    // Grid cells per SVG unit, and where the viewBox's corner lands
+    let scale = (width as Real / box_width).min(height as Real / box_height);
+    let offset_x = (width as Real - box_width * scale) / 2.0;
+    let offset_y = (height as Real - box_height * scale) / 2.0;
+    let polygons: Vec<Vec<Point>> = polygons
+        .iter()
+        .map(|polygon| {
+            polygon
+                .iter()
+                .map(|&(x, y)| {
+                    (
+                        offset_x + (x - min_x) * scale,
+                        offset_y + (y - min_y) * scale,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let samples = (SUPERSAMPLE * SUPERSAMPLE) as Real;
+    Ok((0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let mut inside = 0;
+                    for sy in 0..SUPERSAMPLE {
+                        for sx in 0..SUPERSAMPLE {
+                            let px = x as Real + (sx as Real + 0.5) / SUPERSAMPLE as Real;
+                            let py = y as Real + (sy as Real + 0.5) / SUPERSAMPLE as Real;
+                            let winding = winding_number(&polygons, (px, py));
+                            let filled = if even_odd {
+                                winding % 2 != 0
+                            } else {
+                                winding != 0
+                            };
+                            if filled {
+                                inside += 1;
+                            }
+                        }
+                    }
+                    inside as Real / samples
+                })
+                .collect()
+        })
+        .collect())
+}
+
+// The text of the first <name ...> tag, attributes and all
+fn element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}", name);
+    let mut from = 0;
+    while let Some(found) = text[from..].find(&open) {
+        let start = from + found + open.len();
+        // `<svg` shouldn't match `<svgfoo`
+        match text[start..].chars().next() {
+            Some(c) if c.is_whitespace() || c == '>' || c == '/' => {
+                let end = text[start..].find('>')?;
+                return Some(&text[start..start + end]);
+            }
+            _ => from = start,
+        }
+    }
+    None
+}
+
+// The value of `name="..."` (or single quoted) in a tag's text
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut from = 0;
+    while let Some(found) = tag[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        // `d` shouldn't match the end of `id`
+        if !tag[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let rest = tag[from..].trim_start();
+        let Some(rest) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let quote = rest.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let end = rest[1..].find(quote)?;
+        return Some(&rest[1..1 + end]);
+    }
+    None
+}
+
+// Reads the numbers and flags of path data, where separators are optional wherever
+// the next number can't be read as part of the last one ("1-2.5.5" is 1, -2.5, 0.5)
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Lexer<'a> {
+        Lexer {
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace() || *b == b',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    // A command letter, if that's what comes next
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let b = *self.bytes.get(self.pos)?;
+        // e and E only ever show up inside numbers
+        if b.is_ascii_alphabetic() && b != b'e' && b != b'E' {
+            self.pos += 1;
+            Some(b)
+        } else {
+            None
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos >= self.bytes.len()
+    }
+
+    fn number(&mut self) -> Result<Real, String> {
+        self.skip_separators();
+        let start = self.pos;
+        let digits = |lexer: &mut Lexer| {
+            while lexer.bytes.get(lexer.pos).is_some_and(u8::is_ascii_digit) {
+                lexer.pos += 1;
+            }
+        };
+        if matches!(self.bytes.get(self.pos), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+        digits(self);
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            digits(self);
+        }
+        if matches!(self.bytes.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.bytes.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            digits(self);
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        text.parse().map_err(|_| {
+            let rest = String::from_utf8_lossy(&self.bytes[start..]);
+            format!(
+                "expected a number at `{}`",
+                rest.chars().take(12).collect::<String>()
+            )
+        })
+    }
+
+    // Arc flags are a single 0 or 1, which may run straight into the next number
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        match self.bytes.get(self.pos) {
+            Some(b'0') => {
+                self.pos += 1;
+                Ok(false)
+            }
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            _ => Err("expected an arc flag, 0 or 1".to_string()),
+        }
+    }
+
+    fn point(&mut self) -> Result<Point, String> {
+        Ok((self.number()?, self.number()?))
+    }
+}
+
+// Path data as polygons, one per subpath, curves flattened. Every subpath is taken as
+// closed, as filling it would.
+fn parse_path(data: &str) -> Result<Vec<Vec<Point>>, String> {
+    let mut lexer = Lexer::new(data);
+    let mut polygons: Vec<Vec<Point>> = Vec::new();
+    let mut polygon: Vec<Point> = Vec::new();
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // The last curve's second control point, for the reflection of S and T
+    let mut last_cubic: Option<Point> = None;
+    let mut last_quadratic: Option<Point> = None;
+    let mut command = match lexer.command() {
+        Some(c @ (b'M' | b'm')) => c,
+        _ => return Err("path data has to start with a moveto".to_string()),
+    };
+
+    loop {
+        let relative = command.is_ascii_lowercase();
+        let base = if relative { current } else { (0.0, 0.0) };
+        let offset = |(x, y): Point| (base.0 + x, base.1 + y);
+        let (mut cubic, mut quadratic) = (None, None);
+        match command.to_ascii_uppercase() {
+            b'M' => {
+                if polygon.len() > 2 {
+                    polygons.push(std::mem::take(&mut polygon));
+                }
+                polygon.clear();
+                current = offset(lexer.point()?);
+                start = current;
+                polygon.push(current);
+                // Further pairs after a moveto are linetos
+                command = if relative { b'l' } else { b'L' };
+            }
+            b'L' => {
+                current = offset(lexer.point()?);
+                polygon.push(current);
+            }
+            b'H' => {
+                let x = lexer.number()?;
+                current.0 = if relative { current.0 + x } else { x };
+                polygon.push(current);
+            }
+            b'V' => {
+                let y = lexer.number()?;
+                current.1 = if relative { current.1 + y } else { y };
+                polygon.push(current);
+            }
+            b'C' | b'S' => {
+                let first = if command.eq_ignore_ascii_case(&b'C') {
+                    offset(lexer.point()?)
+                } else {
+                    reflect(last_cubic, current)
+                };
+                let second = offset(lexer.point()?);
+                let end = offset(lexer.point()?);
+                let p0 = current;
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as Real / CURVE_SEGMENTS as Real;
+                    let s = 1.0 - t;
+                    let (a, b, c, d) = (s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t);
+                    polygon.push((
+                        a * p0.0 + b * first.0 + c * second.0 + d * end.0,
+                        a * p0.1 + b * first.1 + c * second.1 + d * end.1,
+                    ));
+                }
+                cubic = Some(second);
+                current = end;
+            }
+            b'Q' | b'T' => {
+                let control = if command.eq_ignore_ascii_case(&b'Q') {
+                    offset(lexer.point()?)
+                } else {
+                    reflect(last_quadratic, current)
+                };
+                let end = offset(lexer.point()?);
+                let p0 = current;
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as Real / CURVE_SEGMENTS as Real;
+                    let s = 1.0 - t;
+                    let (a, b, c) = (s * s, 2.0 * s * t, t * t);
+                    polygon.push((
+                        a * p0.0 + b * control.0 + c * end.0,
+                        a * p0.1 + b * control.1 + c * end.1,
+                    ));
+                }
+                quadratic = Some(control);
+                current = end;
+            }
+            b'A' => {
+                let radii = (lexer.number()?.abs(), lexer.number()?.abs());
+                let rotation = lexer.number()?.to_radians();
+                let large_arc = lexer.flag()?;
+                let sweep = lexer.flag()?;
+                let end = offset(lexer.point()?);
+                arc(
+                    &mut polygon,
+                    current,
+                    end,
+                    radii,
+                    rotation,
+                    large_arc,
+                    sweep,
+                );
+                current = end;
+            }
+            b'Z' => {
+                current = start;
+                if polygon.len() > 2 {
+                    polygons.push(std::mem::take(&mut polygon));
+                }
+                // Drawing on without a moveto starts a new subpath from the same point
+                polygon = vec![start];
+            }
+            _ => return Err(format!("unknown path command `{}`", command as char)),
+        }
+        last_cubic = cubic;
+        last_quadratic = quadratic;
+
+        if lexer.at_end() {
+            break;
+        }
+        match lexer.command() {
+            Some(next) => command = next,
+            // Numbers straight after closepath have nothing to repeat
+            None if command.eq_ignore_ascii_case(&b'Z') => {
+                return Err("numbers after a closepath".to_string())
+            }
+            None => {}
+        }
+    }
+    if polygon.len() > 2 {
+        polygons.push(polygon);
+    }
+    if polygons.is_empty() {
+        return Err("the path encloses nothing".to_string());
+    }
+    Ok(polygons)
+}
+
+// The previous control point mirrored through the current point, or the current point
+// itself when the previous command wasn't the same kind of curve
+fn reflect(control: Option<Point>, current: Point) -> Point {
+    match control {
+        Some((x, y)) => (2.0 * current.0 - x, 2.0 * current.1 - y),
+        None => current,
+    }
+}
+
+// Appends an elliptical arc from `from` to `to`, following the endpoint to center
+// conversion of the SVG spec's implementation notes (F.6.5), radii scaled up if they
+// can't reach
+fn arc(
+    polygon: &mut Vec<Point>,
+    from: Point,
+    to: Point,
+    radii: Point,
+    rotation: Real,
+    large_arc: bool,
+    sweep: bool,
+) {
+    let (mut rx, mut ry) = radii;
+    if rx == 0.0 || ry == 0.0 || from == to {
+        polygon.push(to);
+        return;
+    }
+    let (sin, cos) = rotation.sin_cos();
+    let (hx, hy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let x1 = cos * hx + sin * hy;
+    let y1 = -sin * hx + cos * hy;
+    let reach = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if reach > 1.0 {
+        rx *= reach.sqrt();
+        ry *= reach.sqrt();
+    }
+    let numerator = (rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1).max(0.0);
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut root = (numerator / denominator).sqrt();
+    if large_arc == sweep {
+        root = -root;
+    }
+    let cx1 = root * rx * y1 / ry;
+    let cy1 = -root * ry * x1 / rx;
+    let center = (
+        cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0,
+        sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0,
+    );
+
+    let angle = |ux: Real, uy: Real| uy.atan2(ux);
+    let start = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - start;
+    let tau = 2.0 * crate::consts::PI;
+    if sweep && delta < 0.0 {
+        delta += tau;
+    } else if !sweep && delta > 0.0 {
+        delta -= tau;
+    }
+    for i in 1..=CURVE_SEGMENTS {
+        let theta = start + delta * i as Real / CURVE_SEGMENTS as Real;
+        let (x, y) = (rx * theta.cos(), ry * theta.sin());
+        polygon.push((center.0 + cos * x - sin * y, center.1 + sin * x + cos * y));
+    }
+}
+
+// How many times the polygons wind around `point`, counterclockwise on screen positive
+fn winding_number(polygons: &[Vec<Point>], point: Point) -> i32 {
+    let (px, py) = point;
+    let mut winding = 0;
+    for polygon in polygons {
+        for (i, &(x0, y0)) in polygon.iter().enumerate() {
+            let (x1, y1) = polygon[(i + 1) % polygon.len()];
+            // Which side of the edge the point is on
+            let side = (x1 - x0) * (py - y0) - (px - x0) * (y1 - y0);
+            if y0 <= py && y1 > py && side > 0.0 {
+                winding += 1;
+            } else if y0 > py && y1 <= py && side < 0.0 {
+                winding -= 1;
+            }
+        }
+    }
+    winding
+}
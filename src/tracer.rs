@@ -0,0 +1,138 @@
+use crate::grid::{interpolation_cells, Grid};
+use crate::shape::Shape;
+use crate::Real;
+use serde::{Deserialize, Serialize};
+
+// A passive dye: a concentration from 0 to 1 carried along by the flow without acting back
+// on it. It starts out at 1 where `initial` covers and 0 elsewhere, and is held at 1
+// wherever `sources` cover, and in the jet's cells if the jet names it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tracer {
+    pub color: [u8; 3], // Drawn at full concentration, fading to black at none
+    #[serde(default)]
+    pub sources: Vec<Shape>,
+    #[serde(default)]
+    pub initial: Vec<Shape>,
+}
+
+// One tracer while the sim runs
+#[derive(Debug, Clone)]
+pub struct TracerField {
+    pub color: [u8; 3],
+    pub concentration: Vec<Vec<Real>>,
+    emitter: Vec<Vec<Real>>, // How much of each cell the sources cover
+}
+
+impl TracerField {
+    pub fn new(tracer: &Tracer, width: usize, height: usize) -> TracerField {
+        let mut emitter = vec![vec![0.0; width]; height];
+        for coverage in tracer.sources.iter().map(|s| s.coverage(width, height)) {
+            for (row, coverage_row) in emitter.iter_mut().zip(&coverage) {
+                for (e, &c) in row.iter_mut().zip(coverage_row) {
+                    *e = Real::max(*e, c);
+                }
+            }
+        }
+        let mut concentration = vec![vec![0.0; width]; height];
+        for coverage in tracer.initial.iter().map(|s| s.coverage(width, height)) {
+            for (row, coverage_row) in concentration.iter_mut().zip(&coverage) {
+                for (c, &covered) in row.iter_mut().zip(coverage_row) {
+                    *c = Real::max(*c, covered);
+                }
+            }
+        }
+        let mut field = TracerField {
+            color: tracer.color,
+            concentration,
+            emitter,
+        };
+        field.hold_sources();
+        field
+    }
+
+    // A partly covered cell gets at least the covered share
+    pub fn hold_sources(&mut self) {
+        for (row, emitter_row) in self.concentration.iter_mut().zip(&self.emitter) {
+            for (c, &e) in row.iter_mut().zip(emitter_row) {
+                *c = c.max(e);
+            }
+        }
+    }
+}
+
+// How far the tracers have got from `start`, the mean difference over the cells of each
+pub fn departure(tracers: &[TracerField], start: &[TracerField]) -> Vec<Real> {
+    tracers
+        .iter()
+        .zip(start)
+        .map(|(now, then)| {
+            let cells = now.concentration.iter().flatten();
+            let count = now.concentration.width() * now.concentration.height();
+            cells
+                .zip(then.concentration.iter().flatten())
+                .map(|(a, b)| (a - b).abs())
+                .sum::<Real>()
+                / count as Real
+        })
+        .collect()
+}
+
+// Two gases with their own adiabatic indexes, told apart by one of the tracers: its
+// concentration is how much of a cell is the first gas
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub tracer: usize,
+    pub gammas: [Real; 2],
+}
+
+impl Material {
+    // A mixed cell's gamma, from adding up the internal energy each gas holds at a
+    // pressure, 1 / (gamma - 1) per unit of it, in proportion to its share
+    pub fn gamma(&self, first_share: Real) -> Real {
+        let share = first_share.clamp(0.0, 1.0);
+        let [first, second] = self.gammas;
+        1.0 + 1.0 / (share / (first - 1.0) + (1.0 - share) / (second - 1.0))
+    }
+}
+
+// Semi-Lagrangian: every fluid cell takes the concentration found dt back along the
+// velocity at its center, bilinear between the fluid cells around that point. Stable at
+// any dt, at the price of smearing the dye out a little every step. Cells with no finite
+// velocity, or only obstacles to look back into, keep what they have.
+pub fn advect(
+    concentration: &[Vec<Real>],
+    velocity: &[Vec<(Real, Real)>],
+    solid: &[Vec<bool>],
+    dt: Real,
+    periodic: bool,
+) -> Vec<Vec<Real>> {
+    let (width, height) = (concentration.width(), concentration.height());
+    let mut advected = concentration.to_vec();
+    for (y, row) in advected.iter_mut().enumerate() {
+        for (x, c) in row.iter_mut().enumerate() {
+            let (vx, vy) = velocity[y][x];
+            let (px, py) = (x as Real - vx * dt, y as Real - vy * dt);
+            if solid[y][x] || !px.is_finite() || !py.is_finite() {
+                continue;
+            }
+            let (x0, x1, tx) = interpolation_cells(px, width, periodic);
+            let (y0, y1, ty) = interpolation_cells(py, height, periodic);
+            let (mut sum, mut total_weight) = (0.0, 0.0);
+            for (cx, cy, weight) in [
+                (x0, y0, (1.0 - tx) * (1.0 - ty)),
+                (x1, y0, tx * (1.0 - ty)),
+                (x0, y1, (1.0 - tx) * ty),
+                (x1, y1, tx * ty),
+            ] {
+                if !solid[cy][cx] {
+                    sum += weight * concentration[cy][cx];
+                    total_weight += weight;
+                }
+            }
+            if total_weight > 0.0 {
+                *c = sum / total_weight;
+            }
+        }
+    }
+    advected
+}
@@ -0,0 +1,252 @@
+use crate::grid::{interpolation_cells, Grid};
+use crate::{Cell, Layout, Real, Simulation};
+
+// Fine cells of coarse solution kept around the region, enough for either scheme's stencil
+const RING: usize = 2;
+
+// A closer look at part of the grid without refining all of it: the region resampled onto
+// cells `factor` times finer and simulated on its own for `duration` of sim time, the gas
+// just outside it held at the coarse solution the whole time. It's approximate: nothing
+// that happens inside gets back out, the obstacle is the coarse one cut up, and a second
+// gas or the tracers don't come along.
+#[derive(Debug, Clone, Copy)]
+pub struct Zoom {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub factor: usize,
+    pub duration: Real,
+}
+
+impl Zoom {
+    // From the command line's x, y, width, height, factor and duration
+    pub fn from_list([x, y, width, height, factor, duration]: [Real; 6]) -> Zoom {
+        Zoom {
+            x: x as usize,
+            y: y as usize,
+            width: width as usize,
+            height: height as usize,
+            factor: factor as usize,
+            duration,
+        }
+    }
+
+    // The rectangle with corners on the two cells, both included
+    pub fn between(a: (usize, usize), b: (usize, usize), factor: usize, duration: Real) -> Zoom {
+        Zoom {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0) + 1,
+            height: a.1.abs_diff(b.1) + 1,
+            factor,
+            duration,
+        }
+    }
+
+    pub fn problem(&self, width: usize, height: usize) -> Option<String> {
+        if self.width < 2 || self.height < 2 {
+            return Some(format!(
+                "a zoomed region needs at least 2x2 cells, got {}x{}",
+                self.width, self.height
+            ));
+        }
+        if self.x + self.width > width || self.y + self.height > height {
+            return Some(format!(
+                "the zoomed region {}x{} at ({}, {}) doesn't fit in the {}x{} grid",
+                self.width, self.height, self.x, self.y, width, height
+            ));
+        }
+        if self.factor < 2 {
+            return Some(format!(
+                "zooming needs a factor of at least 2, got {}",
+                self.factor
+            ));
+        }
+        if !(self.duration.is_finite() && self.duration > 0.0) {
+            return Some(format!(
+                "the zoom has to run for a positive time, got {}",
+                self.duration
+            ));
+        }
+        None
+    }
+
+    // Runs the region finer and returns it, without the held ring, as a sim of its own.
+    // One coarse cell is `factor` fine ones across while the speeds stay as they are, so
+    // every fine time unit is 1 / factor of a coarse one: the fine run takes factor times
+    // the duration, and the rates and gravity are scaled to match.
+    pub fn run(&self, sim: &Simulation) -> Simulation {
+        let factor = self.factor as Real;
+        let coarse = sim.centered_grid();
+        let (width, height) = (self.width * self.factor, self.height * self.factor);
+        let (outer_width, outer_height) = (width + 2 * RING, height + 2 * RING);
+        // Where a fine cell's middle is on the coarse grid, in the cell index units the
+        // interpolation takes
+        let coarse_at = |i: usize, j: usize| {
+            (
+                self.x as Real + (i as Real - RING as Real + 0.5) / factor - 0.5,
+                self.y as Real + (j as Real - RING as Real + 0.5) / factor - 0.5,
+            )
+        };
+        // The coarse cell a fine one is part of, the edge one past the edges unless they
+        // wrap around
+        let periodic = sim.settings.periodic;
+        let parent = |i: usize, j: usize| {
+            let (px, py) = coarse_at(i, j);
+            let cell = |f: Real, n: usize| {
+                let f = (f + 0.5).floor();
+                if periodic {
+                    f.rem_euclid(n as Real) as usize % n
+                } else {
+                    (f.max(0.0) as usize).min(n - 1)
+                }
+            };
+            (cell(px, coarse.width()), cell(py, coarse.height()))
+        };
+        let grid: Vec<Vec<Cell>> = (0..outer_height)
+            .map(|j| {
+                (0..outer_width)
+                    .map(|i| sample(&coarse, &sim.solid, coarse_at(i, j), parent(i, j), periodic))
+                    .collect()
+            })
+            .collect();
+        let solid_fraction: Vec<Vec<Real>> = (0..outer_height)
+            .map(|j| {
+                (0..outer_width)
+                    .map(|i| {
+                        let (x, y) = parent(i, j);
+                        sim.solid_fraction[y][x]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut settings = sim.settings;
+        settings.source = None;
+        settings.periodic = false;
+        settings.wrap_edges = false;
+        settings.symmetry = false;
+        settings.local_dt = false;
+        settings.relaxation = 1.0;
+        settings.layout = Layout::Collocated;
+        settings.gravity /= factor;
+        settings.friction /= factor;
+        settings.conduction /= factor;
+        let held = grid.clone();
+        let mut fine = Simulation::new(grid, solid_fraction, settings);
+        fine.dt_safety = sim.dt_safety;
+        let end = self.duration * factor;
+        fine.stop_at = Some(end);
+        let in_ring =
+            |i: usize, j: usize| i < RING || j < RING || i >= RING + width || j >= RING + height;
+        while fine.sim_time < end {
+            let report = fine.step_with(None, |grid, _| {
+                for (j, (row, held_row)) in grid.iter_mut().zip(&held).enumerate() {
+                    for (i, (cell, &hold)) in row.iter_mut().zip(held_row).enumerate() {
+                        if in_ring(i, j) {
+                            *cell = hold;
+                        }
+                    }
+                }
+            });
+            if report.min_density.is_nan() {
+                eprintln!(
+                    "Warning: the zoomed run went NaN at fine step {}, showing it as it is",
+                    fine.step_count
+                );
+                break;
+            }
+        }
+
+        let mut region = Simulation::new(
+            inner(&fine.grid, width, height),
+            inner(&fine.solid_fraction, width, height),
+            settings,
+        );
+        region.step_count = fine.step_count;
+        region.sim_time = sim.sim_time + fine.sim_time / factor;
+        region
+    }
+
+    pub fn report(&self, sim: &Simulation, region: &Simulation) {
+        let (difference, (x, y)) = self.compare(sim, region);
+        println!(
+            "Zoomed into {}x{} cells at ({}, {}), {} times finer, for t = {}: {} fine steps. \
+             Averaged back, the density has moved up to {:.4e} from the coarse state, at ({}, {})",
+            self.width,
+            self.height,
+            self.x,
+            self.y,
+            self.factor,
+            self.duration,
+            region.step_count,
+            difference,
+            x,
+            y
+        );
+    }
+
+    // The fine region averaged back onto the coarse cells, next to the coarse state it
+    // started from: the largest density difference and where it is
+    fn compare(&self, sim: &Simulation, region: &Simulation) -> (Real, (usize, usize)) {
+        let cells = (self.factor * self.factor) as Real;
+        let mut worst = (0.0, (self.x, self.y));
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (cx, cy) = (self.x + x, self.y + y);
+                if sim.solid[cy][cx] {
+                    continue;
+                }
+                let average: Real = (0..self.factor)
+                    .flat_map(|j| (0..self.factor).map(move |i| (i, j)))
+                    .map(|(i, j)| region.grid[y * self.factor + j][x * self.factor + i].density)
+                    .sum::<Real>()
+                    / cells;
+                let difference = (average - sim.grid[cy][cx].density).abs();
+                if difference > worst.0 || difference.is_nan() {
+                    worst = (difference, (cx, cy));
+                }
+            }
+        }
+        worst
+    }
+}
+
+// A fine field without the ring
+fn inner<T: Copy>(rows: &[Vec<T>], width: usize, height: usize) -> Vec<Vec<T>> {
+    rows[RING..RING + height]
+        .iter()
+        .map(|row| row[RING..RING + width].to_vec())
+        .collect()
+}
+
+// Bilinear between the fluid cells around `at`; inside an obstacle, or with only obstacle
+// cells around, the nearest cell as it is
+fn sample(
+    grid: &[Vec<Cell>],
+    solid: &[Vec<bool>],
+    at: (Real, Real),
+    nearest: (usize, usize),
+    periodic: bool,
+) -> Cell {
+    let (x0, x1, tx) = interpolation_cells(at.0, grid.width(), periodic);
+    let (y0, y1, ty) = interpolation_cells(at.1, grid.height(), periodic);
+    let (mut sum, mut total_weight) = (Cell::zero(), 0.0);
+    for (x, y, weight) in [
+        (x0, y0, (1.0 - tx) * (1.0 - ty)),
+        (x1, y0, tx * (1.0 - ty)),
+        (x0, y1, (1.0 - tx) * ty),
+        (x1, y1, tx * ty),
+    ] {
+        if !solid[y][x] {
+            sum = sum + grid[y][x] * weight;
+            total_weight += weight;
+        }
+    }
+    if solid[nearest.1][nearest.0] || total_weight <= 0.0 {
+        grid[nearest.1][nearest.0]
+    } else {
+        sum / total_weight
+    }
+}